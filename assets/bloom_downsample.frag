@@ -0,0 +1,40 @@
+#version 450
+
+#include "common.glsl"
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+
+#ifdef PREFILTER
+layout(push_constant) uniform Parameters {
+    // brightness above which pixels bloom, they fade in over half of it below
+    float threshold;
+};
+#endif
+
+layout(location = 0) in vec2 uv;
+layout(location = 0) out vec4 color;
+
+#ifdef PREFILTER
+vec3 prefilter(vec3 c) {
+    float brightness = max(c.r, max(c.g, c.b));
+    float knee = threshold * 0.5;
+    float soft = clamp(brightness - threshold + knee, 0., 2. * knee);
+    soft = soft * soft / (4. * knee + 0.00001);
+    return c * max(soft, brightness - threshold) / max(brightness, 0.00001);
+}
+#endif
+
+void main() {
+    // four bilinear taps around the center of the target pixel, a 4x4 box of the source
+    vec2 texel = 1. / vec2(textureSize(source, 0));
+    vec3 c = (texture(source, uv + texel * vec2(-1., -1.)).rgb
+        + texture(source, uv + texel * vec2(1., -1.)).rgb
+        + texture(source, uv + texel * vec2(-1., 1.)).rgb
+        + texture(source, uv + texel * vec2(1., 1.)).rgb) * 0.25;
+
+#ifdef PREFILTER
+    c = prefilter(max(c, 0.));
+#endif
+
+    color = vec4(c, 1.);
+}
@@ -0,0 +1,37 @@
+#version 450
+
+#include "common.glsl"
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+
+// the last upsample adds the bloom onto the image itself, scaled down
+#ifdef COMPOSITE
+layout(push_constant) uniform Parameters {
+    float intensity;
+};
+#endif
+
+layout(location = 0) in vec2 uv;
+layout(location = 0) out vec4 color;
+
+void main() {
+    // 3x3 tent over the smaller source, blended additively onto the target
+    vec2 texel = 1. / vec2(textureSize(source, 0));
+    vec3 c = texture(source, uv).rgb * 4.;
+    c += (texture(source, uv + texel * vec2(-1., 0.)).rgb
+        + texture(source, uv + texel * vec2(1., 0.)).rgb
+        + texture(source, uv + texel * vec2(0., -1.)).rgb
+        + texture(source, uv + texel * vec2(0., 1.)).rgb) * 2.;
+    c += texture(source, uv + texel * vec2(-1., -1.)).rgb
+        + texture(source, uv + texel * vec2(1., -1.)).rgb
+        + texture(source, uv + texel * vec2(-1., 1.)).rgb
+        + texture(source, uv + texel * vec2(1., 1.)).rgb;
+    c /= 16.;
+
+#ifdef COMPOSITE
+    c *= intensity;
+#endif
+
+    // zero alpha leaves the alpha of the target as it is
+    color = vec4(c, 0.);
+}
@@ -0,0 +1,28 @@
+#ifndef COLOR_GLSL
+#define COLOR_GLSL
+
+vec3 invert(vec3 color) {
+    return 1. - color;
+}
+
+float luminance(vec3 color) {
+    return dot(color, vec3(0.2126, 0.7152, 0.0722));
+}
+
+vec3 linear_to_srgb(vec3 color) {
+    vec3 low = color * 12.92;
+    vec3 high = 1.055 * pow(color, vec3(1. / 2.4)) - 0.055;
+    return mix(low, high, step(0.0031308, color));
+}
+
+// the last pass of the chain writes to the final image, which only encodes
+// to sRGB by itself if it has an sRGB format, otherwise ENCODE_SRGB is defined
+vec3 encode_output(vec3 color) {
+#ifdef ENCODE_SRGB
+    return linear_to_srgb(clamp(color, 0., 1.));
+#else
+    return color;
+#endif
+}
+
+#endif
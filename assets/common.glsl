@@ -0,0 +1,6 @@
+#ifndef COMMON_GLSL
+#define COMMON_GLSL
+
+#extension GL_ARB_separate_shader_objects : enable
+
+#endif
@@ -1,5 +1,6 @@
 #version 450
-#extension GL_ARB_separate_shader_objects : enable
+
+#include "common.glsl"
 
 layout(location = 0) out vec2 uv;
 
@@ -0,0 +1,138 @@
+#version 450
+
+#include "common.glsl"
+#include "color.glsl"
+
+// FXAA 3.11 on the tonemapped image, edges are searched for along the
+// direction they run in and blended across by how far away their ends are
+#define EDGE_THRESHOLD_MIN 0.0312
+#define EDGE_THRESHOLD_MAX 0.125
+#define SUBPIXEL_QUALITY 0.75
+#define SEARCH_STEPS 12
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+
+layout(location = 0) in vec2 uv;
+layout(location = 0) out vec4 color;
+
+// in pixels, the search speeds up the further it gets
+const float SEARCH_STEP[SEARCH_STEPS] = float[](1., 1., 1., 1., 1., 1.5, 2., 2., 2., 2., 4., 8.);
+
+// perceptual luma, the source is linear
+float luma(vec3 color) {
+    return sqrt(luminance(clamp(color, 0., 1.)));
+}
+
+float luma_at(vec2 uv) {
+    return luma(textureLod(source, uv, 0.).rgb);
+}
+
+void main() {
+    vec2 texel = 1. / vec2(textureSize(source, 0));
+    vec4 center = textureLod(source, uv, 0.);
+
+    float luma_center = luma(center.rgb);
+    float luma_up = luma(textureLodOffset(source, uv, 0., ivec2(0, -1)).rgb);
+    float luma_down = luma(textureLodOffset(source, uv, 0., ivec2(0, 1)).rgb);
+    float luma_left = luma(textureLodOffset(source, uv, 0., ivec2(-1, 0)).rgb);
+    float luma_right = luma(textureLodOffset(source, uv, 0., ivec2(1, 0)).rgb);
+
+    float luma_min = min(luma_center, min(min(luma_up, luma_down), min(luma_left, luma_right)));
+    float luma_max = max(luma_center, max(max(luma_up, luma_down), max(luma_left, luma_right)));
+    float range = luma_max - luma_min;
+    if (range < max(EDGE_THRESHOLD_MIN, luma_max * EDGE_THRESHOLD_MAX)) {
+        color = vec4(encode_output(center.rgb), center.a);
+        return;
+    }
+
+    float luma_up_left = luma(textureLodOffset(source, uv, 0., ivec2(-1, -1)).rgb);
+    float luma_up_right = luma(textureLodOffset(source, uv, 0., ivec2(1, -1)).rgb);
+    float luma_down_left = luma(textureLodOffset(source, uv, 0., ivec2(-1, 1)).rgb);
+    float luma_down_right = luma(textureLodOffset(source, uv, 0., ivec2(1, 1)).rgb);
+
+    float luma_up_down = luma_up + luma_down;
+    float luma_left_right = luma_left + luma_right;
+    float luma_left_corners = luma_up_left + luma_down_left;
+    float luma_right_corners = luma_up_right + luma_down_right;
+    float luma_up_corners = luma_up_left + luma_up_right;
+    float luma_down_corners = luma_down_left + luma_down_right;
+
+    float edge_horizontal = abs(luma_left_corners - 2. * luma_left)
+        + 2. * abs(luma_up_down - 2. * luma_center)
+        + abs(luma_right_corners - 2. * luma_right);
+    float edge_vertical = abs(luma_up_corners - 2. * luma_up)
+        + 2. * abs(luma_left_right - 2. * luma_center)
+        + abs(luma_down_corners - 2. * luma_down);
+    bool horizontal = edge_horizontal >= edge_vertical;
+
+    // the edge lies between the center and whichever neighbour across it differs the most
+    float luma_negative = horizontal ? luma_up : luma_left;
+    float luma_positive = horizontal ? luma_down : luma_right;
+    float gradient_negative = luma_negative - luma_center;
+    float gradient_positive = luma_positive - luma_center;
+    bool negative = abs(gradient_negative) >= abs(gradient_positive);
+    float gradient_scaled = 0.25 * max(abs(gradient_negative), abs(gradient_positive));
+
+    float step_length = horizontal ? texel.y : texel.x;
+    float luma_local_average;
+    if (negative) {
+        step_length = -step_length;
+        luma_local_average = 0.5 * (luma_negative + luma_center);
+    } else {
+        luma_local_average = 0.5 * (luma_positive + luma_center);
+    }
+
+    vec2 edge_uv = uv;
+    if (horizontal) {
+        edge_uv.y += 0.5 * step_length;
+    } else {
+        edge_uv.x += 0.5 * step_length;
+    }
+
+    // walk along the edge both ways until the luma no longer matches it
+    vec2 offset = horizontal ? vec2(texel.x, 0.) : vec2(0., texel.y);
+    vec2 uv1 = edge_uv - offset;
+    vec2 uv2 = edge_uv + offset;
+    float luma_end1 = luma_at(uv1) - luma_local_average;
+    float luma_end2 = luma_at(uv2) - luma_local_average;
+    bool reached1 = abs(luma_end1) >= gradient_scaled;
+    bool reached2 = abs(luma_end2) >= gradient_scaled;
+    for (int i = 1; i < SEARCH_STEPS && !(reached1 && reached2); i++) {
+        if (!reached1) {
+            uv1 -= offset * SEARCH_STEP[i];
+            luma_end1 = luma_at(uv1) - luma_local_average;
+            reached1 = abs(luma_end1) >= gradient_scaled;
+        }
+        if (!reached2) {
+            uv2 += offset * SEARCH_STEP[i];
+            luma_end2 = luma_at(uv2) - luma_local_average;
+            reached2 = abs(luma_end2) >= gradient_scaled;
+        }
+    }
+
+    float distance1 = horizontal ? uv.x - uv1.x : uv.y - uv1.y;
+    float distance2 = horizontal ? uv2.x - uv.x : uv2.y - uv.y;
+    bool closer1 = distance1 < distance2;
+    float pixel_offset = 0.5 - min(distance1, distance2) / (distance1 + distance2);
+
+    // only blend if the closer end of the edge goes the other way than the center
+    bool center_smaller = luma_center < luma_local_average;
+    bool correct_variation = ((closer1 ? luma_end1 : luma_end2) < 0.) != center_smaller;
+    float final_offset = correct_variation ? pixel_offset : 0.;
+
+    // edges thinner than a pixel are blended by how much the center stands out
+    float luma_average = (2. * (luma_up_down + luma_left_right) + luma_left_corners + luma_right_corners) / 12.;
+    float subpixel = clamp(abs(luma_average - luma_center) / range, 0., 1.);
+    subpixel = (3. - 2. * subpixel) * subpixel * subpixel;
+    final_offset = max(final_offset, subpixel * subpixel * SUBPIXEL_QUALITY);
+
+    vec2 final_uv = uv;
+    if (horizontal) {
+        final_uv.y += final_offset * step_length;
+    } else {
+        final_uv.x += final_offset * step_length;
+    }
+
+    vec4 pixel = textureLod(source, final_uv, 0.);
+    color = vec4(encode_output(pixel.rgb), pixel.a);
+}
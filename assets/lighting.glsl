@@ -0,0 +1,45 @@
+#ifndef LIGHTING_GLSL
+#define LIGHTING_GLSL
+
+// depth bias against shadow acne, more of it where the light grazes the surface
+#define MIN_SHADOW_BIAS 0.0005
+#define MAX_SHADOW_BIAS 0.005
+
+// `direction` is the unit direction the light travels in
+float diffuse(vec3 norm, vec3 direction) {
+    return max(dot(-norm, direction), 0.);
+}
+
+// fraction of the light reaching a fragment at `world`, from the first cascade whose shadow map
+// covers it and the 3x3 texels around it, `cascade` is its index or `CASCADES` if there is none
+//
+// the shadow maps of `CASCADES` cascades are in rows of `CASCADE_COLUMNS` in `atlas`
+float shadow(sampler2DShadow atlas, mat4 transforms[CASCADES], vec4 world, float diffuse, out int cascade) {
+    vec2 grid = vec2(CASCADE_COLUMNS, (CASCADES + CASCADE_COLUMNS - 1) / CASCADE_COLUMNS);
+    vec2 texel = 1. / vec2(textureSize(atlas, 0));
+    // the filter reaches a texel and a half into the neighbouring cascades
+    vec2 margin = 2. * texel * grid;
+
+    for (cascade = 0; cascade < CASCADES; cascade++) {
+        vec4 coord = transforms[cascade] * world;
+        vec3 ndc = coord.xyz / coord.w;
+        vec2 uv = ndc.xy * 0.5 + 0.5;
+        // nothing beyond the far end casts shadows onto it
+        if (any(lessThan(uv, margin)) || any(greaterThan(uv, 1. - margin)) || ndc.z > 1.) {
+            continue;
+        }
+
+        uv = (vec2(cascade % CASCADE_COLUMNS, cascade / CASCADE_COLUMNS) + uv) / grid;
+        float depth = ndc.z - mix(MAX_SHADOW_BIAS, MIN_SHADOW_BIAS, diffuse);
+        float lit = 0.;
+        for (int y = -1; y <= 1; y++) {
+            for (int x = -1; x <= 1; x++) {
+                lit += texture(atlas, vec3(uv + vec2(x, y) * texel, depth));
+            }
+        }
+        return lit / 9.;
+    }
+    return 1.;
+}
+
+#endif
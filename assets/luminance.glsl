@@ -0,0 +1,24 @@
+#ifndef LUMINANCE_GLSL
+#define LUMINANCE_GLSL
+
+// log2 of the darkest luminance the histogram tells apart, anything below only counts as black
+#ifndef MIN_LOG_LUMINANCE
+#define MIN_LOG_LUMINANCE -10.
+#endif
+
+// log2 of the brightest luminance minus MIN_LOG_LUMINANCE, anything above lands in the last bin
+#ifndef LOG_LUMINANCE_RANGE
+#define LOG_LUMINANCE_RANGE 22.
+#endif
+
+const uint BINS = 256;
+
+// filled by histogram.comp, averaged and cleared again by average_luminance.comp
+layout(std430, set = 0, binding = 1) buffer Luminance {
+    // the first bin counts black pixels, which are left out of the average
+    uint histogram[BINS];
+    // adapted over time, zero until the first frame is averaged
+    float average_luminance;
+};
+
+#endif
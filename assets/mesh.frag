@@ -1,11 +1,54 @@
 #version 450
-#extension GL_ARB_separate_shader_objects : enable
 
-layout(location = 0) flat in vec3 frag_norm;
+#include "common.glsl"
+#include "lighting.glsl"
+
+layout(set = 0, binding = 0) uniform sampler2D albedo;
+
+layout(set = 1, binding = 1) uniform Light {
+    // into the clip space of the shadow map of each cascade, the nearest first
+    mat4 light_transforms[CASCADES];
+    // unit length
+    vec4 light_direction;
+    vec4 light_color;
+};
+layout(set = 1, binding = 2) uniform sampler2DShadow shadow_atlas;
+
+// shared with mesh.vert
+layout(push_constant) uniform Instance {
+    mat4 model;
+    // linear, multiplies the albedo
+    vec4 color;
+} PushConstants;
+
+layout(location = 0) in vec3 frag_norm;
+layout(location = 1) in vec2 frag_uv;
+layout(location = 2) in vec4 frag_world;
 layout(location = 0) out vec4 color;
+#ifdef VELOCITY
+layout(location = 3) in vec4 frag_current;
+layout(location = 4) in vec4 frag_previous;
+// in texture coordinates, from where the fragment was last frame to where it is now
+layout(location = 1) out vec2 velocity;
+#endif
+
+#ifdef DEBUG_CASCADES
+const vec3 CASCADE_COLORS[4] = vec3[](vec3(1., 0.3, 0.3), vec3(0.3, 1., 0.3), vec3(0.3, 0.3, 1.), vec3(1., 1., 0.3));
+#endif
 
 void main() {
-    float light = dot(-frag_norm, vec3(0., 1.,0.));
-    float dist = length(frag_norm);
-    color = vec4(vec3(light*dist), 1.);
-}
\ No newline at end of file
+    // interpolated normals are no longer unit length
+    vec3 norm = normalize(frag_norm);
+    float light = diffuse(norm, light_direction.xyz);
+    int cascade;
+    light *= shadow(shadow_atlas, light_transforms, frag_world, light, cascade);
+    color = vec4(light * light_color.rgb * PushConstants.color.rgb * texture(albedo, frag_uv).rgb, 1.);
+#ifdef DEBUG_CASCADES
+    if (cascade < CASCADES) {
+        color.rgb *= CASCADE_COLORS[cascade % 4];
+    }
+#endif
+#ifdef VELOCITY
+    velocity = (frag_current.xy / frag_current.w - frag_previous.xy / frag_previous.w) * 0.5;
+#endif
+}
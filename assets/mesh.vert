@@ -1,14 +1,42 @@
 #version 450
-#extension GL_ARB_separate_shader_objects : enable
+
+#include "common.glsl"
 
 layout(location = 0) in vec3 position;
-layout(location = 0) flat out vec3 frag_norm;
+layout(location = 1) in vec3 normal;
+layout(location = 2) in vec2 tex_coord;
+layout(location = 0) out vec3 frag_norm;
+layout(location = 1) out vec2 frag_uv;
+layout(location = 2) out vec4 frag_world;
+#ifdef VELOCITY
+layout(location = 3) out vec4 frag_current;
+layout(location = 4) out vec4 frag_previous;
+#endif
 
-layout(push_constant) uniform Transform {
+layout(set = 1, binding = 0) uniform Camera {
+    // includes the jitter of the frame
     mat4 view;
+    // both without jitter, objects are assumed to stay where they are
+    mat4 unjittered_view;
+    mat4 previous_view;
+};
+
+// shared with mesh.frag
+layout(push_constant) uniform Instance {
+    mat4 model;
+    // linear, multiplies the albedo
+    vec4 color;
 } PushConstants;
 
 void main() {
-    frag_norm = normalize(position);
-    gl_Position = PushConstants.view * vec4(position, 1.);
-}
\ No newline at end of file
+    mat3 normal_matrix = transpose(inverse(mat3(PushConstants.model)));
+    frag_norm = normal_matrix * normal;
+    frag_uv = tex_coord;
+    vec4 world = PushConstants.model * vec4(position, 1.);
+    gl_Position = view * world;
+    frag_world = world;
+#ifdef VELOCITY
+    frag_current = unjittered_view * world;
+    frag_previous = previous_view * world;
+#endif
+}
@@ -0,0 +1,14 @@
+#version 450
+
+#include "common.glsl"
+#include "color.glsl"
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+
+layout(location = 0) in vec2 uv;
+layout(location = 0) out vec4 color;
+
+void main() {
+    vec4 pixel = texture(source, uv);
+    color = vec4(encode_output(pixel.rgb), pixel.a);
+}
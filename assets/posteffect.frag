@@ -1,16 +1,22 @@
 #version 450
-#extension GL_ARB_separate_shader_objects : enable
 
-layout(location = 0) in vec2 uv;
-layout(location = 0) out vec4 color;
+#include "common.glsl"
+#include "color.glsl"
+
+layout(set = 0, binding = 0) uniform sampler2D source;
 
+layout(push_constant) uniform Parameters {
+    // of the sine the image is warped with, in radians per pixel and texture coordinates
+    float frequency;
+    float amplitude;
+};
 
-layout(set = 0, binding = 1) uniform sampler colorsampler;
-layout(set = 0, binding = 0) uniform texture2D colormap;
+layout(location = 0) in vec2 uv;
+layout(location = 0) out vec4 color;
 
 void main() {
-    vec2 uv_ = vec2(uv.x, uv.y + sin(gl_FragCoord.x*0.05)*0.05);
-    vec4 pixel = texture(sampler2D(colormap, colorsampler), uv_);
-    
-    color = vec4(1.-pixel.rgb, pixel.a);
-}
\ No newline at end of file
+    vec2 uv_ = vec2(uv.x, uv.y + sin(gl_FragCoord.x*frequency)*amplitude);
+    vec4 pixel = texture(source, uv_);
+
+    color = vec4(encode_output(invert(pixel.rgb)), pixel.a);
+}
@@ -0,0 +1,14 @@
+#version 450
+
+#include "common.glsl"
+
+layout(location = 0) in vec3 position;
+
+layout(push_constant) uniform Transform {
+    // the light's transform times the model
+    mat4 transform;
+} PushConstants;
+
+void main() {
+    gl_Position = PushConstants.transform * vec4(position, 1.);
+}
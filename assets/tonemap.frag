@@ -0,0 +1,68 @@
+#version 450
+
+#include "common.glsl"
+#include "color.glsl"
+
+// operators OPERATOR can be set to
+#define REINHARD 0
+#define ACES 1
+#define UNCHARTED2 2
+
+#ifndef OPERATOR
+#define OPERATOR ACES
+#endif
+
+// with AUTO_EXPOSURE defined the average luminance of the source is mapped to
+// middle gray before exposure is applied, the graph measures it for this pass
+#ifdef AUTO_EXPOSURE
+#include "luminance.glsl"
+
+const float MIDDLE_GRAY = 0.18;
+#endif
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+
+layout(push_constant) uniform Parameters {
+    // in stops, 0 leaves the hdr color as is
+    float exposure;
+};
+
+layout(location = 0) in vec2 uv;
+layout(location = 0) out vec4 color;
+
+// Narkowicz's fit of the ACES filmic curve
+vec3 aces(vec3 x) {
+    return clamp((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14), 0., 1.);
+}
+
+// Hable's filmic curve from Uncharted 2
+vec3 uncharted2_curve(vec3 x) {
+    const float A = 0.15, B = 0.50, C = 0.10, D = 0.20, E = 0.02, F = 0.30;
+    return ((x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F)) - E / F;
+}
+
+vec3 uncharted2(vec3 x) {
+    const float WHITE = 11.2;
+    return uncharted2_curve(2. * x) / uncharted2_curve(vec3(WHITE));
+}
+
+void main() {
+    vec4 pixel = texture(source, uv);
+    float scale = exp2(exposure);
+#ifdef AUTO_EXPOSURE
+    scale *= MIDDLE_GRAY / max(average_luminance, exp2(MIN_LOG_LUMINANCE));
+#endif
+    vec3 hdr = max(pixel.rgb, 0.) * scale;
+
+#if OPERATOR == REINHARD
+    vec3 ldr = hdr / (1. + hdr);
+#elif OPERATOR == ACES
+    vec3 ldr = aces(hdr);
+#elif OPERATOR == UNCHARTED2
+    vec3 ldr = uncharted2(hdr);
+#else
+#error Unknown OPERATOR
+#endif
+
+    color = vec4(encode_output(ldr), pixel.a);
+}
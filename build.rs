@@ -0,0 +1,39 @@
+//! Generates the table of shader sources compiled into the binary with `embed-shaders`.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+/// Shaders and the files they include, relative to `dir`.
+fn shader_files(dir: &Path, prefix: &str, files: &mut Vec<(String, PathBuf)>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        let name = format!("{}{}", prefix, path.file_name().unwrap().to_str().unwrap());
+
+        if path.is_dir() {
+            shader_files(&path, &format!("{}/", name), files);
+        } else if let Some("vert" | "frag" | "comp" | "glsl") = path.extension().and_then(|e| e.to_str()) {
+            files.push((name, path));
+        }
+    }
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed=assets");
+
+    let mut files = Vec::new();
+    if env::var_os("CARGO_FEATURE_EMBED_SHADERS").is_some() {
+        let assets = Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join("assets");
+        shader_files(&assets, "", &mut files);
+        files.sort();
+    }
+
+    let entries: String = files
+        .iter()
+        .map(|(name, path)| format!("    ({:?}, include_str!({:?})),\n", name, path))
+        .collect();
+
+    let out = Path::new(&env::var("OUT_DIR").unwrap()).join("embedded_shaders.rs");
+    fs::write(out, format!("&[\n{}]\n", entries)).unwrap();
+}
@@ -0,0 +1,96 @@
+use std::ops::Range;
+
+use nalgebra::{Matrix4, Point3, Vector2, Vector3};
+
+#[derive(Clone, Copy, Debug)]
+pub struct Camera {
+    pub pitch: f32,
+    pub yaw: f32,
+    pub pos: Point3<f32>,
+    pub aspect: f32,
+    pub fov: f32,
+    pub near: f32,
+    pub far: f32,
+    /// Offset of the image in normalized device coordinates, changed every frame
+    /// for temporal anti-aliasing.
+    pub jitter: Vector2<f32>,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            pitch: 0.,
+            yaw: 0.,
+            pos: Point3::new(0., 0., 0.),
+            aspect: 1.,
+            fov: 45.,
+            near: 0.1,
+            far: 1.,
+            jitter: Vector2::zeros(),
+        }
+    }
+}
+
+impl Camera {
+    pub fn new(aspect: f32) -> Self {
+        Self {
+            aspect,
+            far: 20.,
+            ..Default::default()
+        }
+    }
+    pub fn get_view_direction(&self) -> Vector3<f32> {
+        Vector3::new(
+            self.pitch.cos() * self.yaw.sin(),
+            self.pitch.cos() * self.yaw.cos(),
+            self.pitch.sin(),
+        )
+    }
+    pub fn get_view(&self) -> Matrix4<f32> {
+        Matrix4::look_at_lh(
+            &self.pos,
+            &Point3::from(self.get_view_direction()+self.pos.coords),
+            &Vector3::new(0., 0., 1.),
+        )
+    }
+    pub fn get_projection(&self) -> Matrix4<f32> {
+        let mut projection = Matrix4::new_perspective(self.aspect, self.fov, self.near, self.far);
+        // adding `jitter * w` in clip space moves everything by `jitter` after the divide
+        let w = projection.row(3).clone_owned();
+        projection.set_row(0, &(projection.row(0) + w * self.jitter.x));
+        projection.set_row(1, &(projection.row(1) + w * self.jitter.y));
+        projection
+    }
+    pub fn get_transform(&self) -> Matrix4<f32> {
+        self.get_projection() * self.get_view()
+    }
+    /// Center and radius of a sphere around what the camera sees within `range`,
+    /// in fractions of the distance from the closest to the furthest visible point.
+    pub fn get_bounding_sphere(&self, range: Range<f32>) -> (Point3<f32>, f32) {
+        let inverse = self.get_unjittered_transform().try_inverse().unwrap();
+        // corners of the frustum, clip space depth is `0..w`, along each edge the depth grows linearly
+        let corners: Vec<_> = (0..8)
+            .map(|i| {
+                let (x, y) = (if i & 1 == 0 { -1. } else { 1. }, if i & 2 == 0 { -1. } else { 1. });
+                let near = inverse.transform_point(&Point3::new(x, y, 0.));
+                let far = inverse.transform_point(&Point3::new(x, y, 1.));
+                let t = if i & 4 == 0 { range.start } else { range.end };
+                near + (far - near) * t
+            })
+            .collect();
+        let center = Point3::from(corners.iter().map(|corner| corner.coords).sum::<Vector3<f32>>() / 8.);
+        let radius = corners
+            .iter()
+            .map(|corner| nalgebra::distance(&center, corner))
+            .fold(0., f32::max);
+        (center, radius)
+    }
+    /// Transform without the jitter, the velocity of the mesh pass is measured with it.
+    pub fn get_unjittered_transform(&self) -> Matrix4<f32> {
+        Camera {
+            jitter: Vector2::zeros(),
+            ..*self
+        }
+        .get_transform()
+    }
+}
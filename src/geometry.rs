@@ -0,0 +1,182 @@
+use genmesh::generators::{IndexedPolygon, SharedVertex};
+use nalgebra::Vector3;
+use rendy::{
+    command::QueueId,
+    factory::Factory,
+    hal,
+    mesh::{Mesh, Normal, Position, TexCoord},
+};
+
+use std::path::Path;
+
+/// Vertex data of a single mesh, kept on the CPU until it is uploaded with `build`.
+#[derive(Clone, Debug, Default)]
+pub struct MeshData {
+    pub name: String,
+    pub indices: Vec<u32>,
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub tex_coords: Vec<[f32; 2]>,
+    /// Index of the material in whatever the mesh was imported from.
+    pub material: Option<usize>,
+}
+
+impl MeshData {
+    /// Uploads the mesh, every attribute ends up in its own vertex buffer.
+    pub fn build<B: hal::Backend>(&self, factory: &Factory<B>, queue: QueueId) -> Mesh<B> {
+        let positions: Vec<_> = self.positions.iter().cloned().map(Position).collect();
+        let normals: Vec<_> = self.normals.iter().cloned().map(Normal).collect();
+        let tex_coords: Vec<_> = self.tex_coords.iter().cloned().map(TexCoord).collect();
+
+        // the builder owns the vertices, it outlives them as the returned temporary otherwise
+        Mesh::<B>::builder()
+            .with_indices(&self.indices[..])
+            .with_vertices(positions)
+            .with_vertices(normals)
+            .with_vertices(tex_coords)
+            .build(queue, factory)
+            .unwrap()
+    }
+
+    /// Smooth normals averaged from the faces around each vertex.
+    pub fn compute_normals(&mut self) {
+        let mut normals = vec![Vector3::zeros(); self.positions.len()];
+        for face in self.indices.chunks_exact(3) {
+            let [a, b, c] = [face[0], face[1], face[2]].map(|i| Vector3::from(self.positions[i as usize]));
+            // not normalized, so larger faces weigh more
+            let normal = (b - a).cross(&(c - a));
+            for &i in face {
+                normals[i as usize] += normal;
+            }
+        }
+
+        self.normals = normals
+            .into_iter()
+            .map(|n| n.try_normalize(0.).unwrap_or_else(Vector3::z).into())
+            .collect();
+    }
+}
+
+/// The unit icosphere the example scene is made of.
+pub fn icosphere() -> MeshData {
+    let icosphere = genmesh::generators::IcoSphere::subdivide(4);
+    let indices: Vec<_> = genmesh::Vertices::vertices(icosphere.indexed_polygon_iter())
+        .map(|i| i as u32)
+        .collect();
+    let positions: Vec<[f32; 3]> = icosphere
+        .shared_vertex_iter()
+        .map(|v| v.pos.into())
+        .collect();
+    let normals = icosphere
+        .shared_vertex_iter()
+        .map(|v| v.normal.into())
+        .collect();
+    // equirectangular mapping, vertices on the seam are shared so one column of faces wraps around
+    let tex_coords = positions
+        .iter()
+        .map(|p| {
+            let u = p[1].atan2(p[0]) / (2. * std::f32::consts::PI) + 0.5;
+            let v = p[2].clamp(-1., 1.).acos() / std::f32::consts::PI;
+            [u, v]
+        })
+        .collect();
+
+    MeshData {
+        name: "icosphere".to_string(),
+        indices,
+        tex_coords,
+        positions,
+        normals,
+        material: None,
+    }
+}
+
+/// Loads every group of a Wavefront OBJ file as a separate mesh, skipping groups without faces.
+///
+/// Faces are triangulated, missing normals are computed and missing texture coordinates are zeroed.
+pub fn load_obj(path: &Path) -> Result<Vec<MeshData>, tobj::LoadError> {
+    let (models, _materials) = tobj::load_obj(path, &OBJ_LOAD_OPTIONS)?;
+    Ok(obj_meshes(models))
+}
+
+const OBJ_LOAD_OPTIONS: tobj::LoadOptions = tobj::LoadOptions {
+    single_index: true,
+    triangulate: true,
+    ignore_points: true,
+    ignore_lines: true,
+};
+
+fn obj_meshes(models: Vec<tobj::Model>) -> Vec<MeshData> {
+    models
+        .into_iter()
+        // empty meshes can't be uploaded
+        .filter(|model| !model.mesh.indices.is_empty())
+        .map(|model| {
+            let mesh = model.mesh;
+            let vertex_count = mesh.positions.len() / 3;
+
+            let mut data = MeshData {
+                name: model.name,
+                indices: mesh.indices,
+                positions: mesh.positions.chunks_exact(3).map(|p| [p[0], p[1], p[2]]).collect(),
+                normals: mesh.normals.chunks_exact(3).map(|n| [n[0], n[1], n[2]]).collect(),
+                // OBJ has the origin of texture coordinates at the bottom left
+                tex_coords: mesh.texcoords.chunks_exact(2).map(|t| [t[0], 1. - t[1]]).collect(),
+                material: None,
+            };
+
+            if data.normals.len() != vertex_count {
+                data.compute_normals();
+            }
+            if data.tex_coords.len() != vertex_count {
+                data.tex_coords = vec![[0., 0.]; vertex_count];
+            }
+
+            data
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn obj_groups_become_meshes() {
+        let obj = "\
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+vn 0 0 1
+g textured
+f 1/1/1 2/2/1 3/3/1 4/4/1
+g plain
+f 1 3 2
+g empty
+";
+        let (models, _materials) = tobj::load_obj_buf(&mut obj.as_bytes(), &OBJ_LOAD_OPTIONS, |_| {
+            Err(tobj::LoadError::OpenFileFailed)
+        })
+        .unwrap();
+        let meshes = obj_meshes(models);
+
+        let names: Vec<_> = meshes.iter().map(|mesh| mesh.name.as_str()).collect();
+        assert_eq!(names, ["textured", "plain"]);
+
+        // the quad is triangulated and its texture coordinates flipped to a top left origin
+        let textured = &meshes[0];
+        assert_eq!(textured.indices.len(), 6);
+        assert_eq!(textured.tex_coords, [[0., 1.], [1., 1.], [1., 0.], [0., 0.]]);
+        assert!(textured.normals.iter().all(|&n| n == [0., 0., 1.]));
+
+        // the face is wound the other way, so the computed normals point away
+        let plain = &meshes[1];
+        assert_eq!(plain.tex_coords, [[0., 0.]; 3]);
+        assert!(plain.normals.iter().all(|&n| n == [0., 0., -1.]));
+    }
+}
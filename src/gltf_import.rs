@@ -0,0 +1,404 @@
+use nalgebra::Matrix4;
+
+use std::path::Path;
+
+use crate::{
+    geometry::MeshData,
+    scene::{Object, Scene},
+    texture::TextureData,
+};
+
+/// Metallic-roughness material of a glTF primitive.
+#[derive(Clone, Debug)]
+pub struct Material {
+    pub name: Option<String>,
+    pub base_color: [f32; 4],
+    /// Index into `GltfScene::textures`.
+    pub base_color_texture: Option<usize>,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            name: None,
+            base_color: [1., 1., 1., 1.],
+            base_color_texture: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SceneNode {
+    pub name: Option<String>,
+    /// Transform relative to the parent node.
+    pub transform: Matrix4<f32>,
+    /// Indices into `GltfScene::meshes`, one for every primitive of the glTF mesh.
+    pub meshes: Vec<usize>,
+    /// Indices into `GltfScene::nodes`.
+    pub children: Vec<usize>,
+}
+
+/// Everything imported from a glTF file, still on the CPU.
+#[derive(Clone, Debug, Default)]
+pub struct GltfScene {
+    pub meshes: Vec<MeshData>,
+    pub materials: Vec<Material>,
+    pub textures: Vec<TextureData>,
+    pub nodes: Vec<SceneNode>,
+    /// Root nodes of the default scene.
+    pub roots: Vec<usize>,
+}
+
+/// From glTF, right-handed with `+Y` up and models facing `+Z`, into the world. The camera looks
+/// along `-Y` with `-X` to the right and `-Z` up on screen there, which takes a reflection to get to.
+///
+/// A model seen from the front ends up facing a camera with the default orientation.
+pub fn gltf_to_world() -> Matrix4<f32> {
+    Matrix4::new(
+        -1., 0., 0., 0.,
+        0., 0., 1., 0.,
+        0., -1., 0., 0.,
+        0., 0., 0., 1.,
+    )
+}
+
+impl GltfScene {
+    /// Objects for every node of the default scene, meshes keep their indices into `meshes`.
+    /// The root nodes are children of an object converting them into the world, see `gltf_to_world`.
+    ///
+    /// The first primitive of a node is drawn by the node itself, any further ones
+    /// become children with an identity transform.
+    pub fn to_scene(&self) -> Scene {
+        let mut scene = Scene::default();
+        let root = scene.add(Object {
+            name: "glTF".to_string(),
+            mesh: None,
+            texture: None,
+            color: [1.; 4],
+            transform: gltf_to_world(),
+            parent: None,
+        });
+        let mut stack: Vec<_> = self.roots.iter().rev().map(|&node| (node, Some(root))).collect();
+
+        while let Some((index, parent)) = stack.pop() {
+            let node = &self.nodes[index];
+            let name = node.name.clone().unwrap_or_default();
+            let material = node.meshes.first().map(|&mesh| self.material(mesh)).unwrap_or_default();
+            let id = scene.add(Object {
+                name: name.clone(),
+                mesh: node.meshes.first().cloned(),
+                texture: material.base_color_texture,
+                color: material.base_color,
+                transform: node.transform,
+                parent,
+            });
+
+            for &mesh in node.meshes.iter().skip(1) {
+                let material = self.material(mesh);
+                scene.add(Object {
+                    name: name.clone(),
+                    mesh: Some(mesh),
+                    texture: material.base_color_texture,
+                    color: material.base_color,
+                    transform: Matrix4::identity(),
+                    parent: Some(id),
+                });
+            }
+            stack.extend(node.children.iter().rev().map(|&child| (child, Some(id))));
+        }
+
+        scene
+    }
+
+    /// Material of `mesh`, the default one when it has none.
+    fn material(&self, mesh: usize) -> Material {
+        self.meshes[mesh]
+            .material
+            .map_or_else(Material::default, |material| self.materials[material].clone())
+    }
+}
+
+/// Imports the default scene of a `.gltf` or `.glb` file.
+///
+/// Buffers and images may be embedded or stored next to the file.
+/// Only triangle primitives are imported. Samplers are ignored, every texture is filtered
+/// linearly with repeating coordinates, see `TextureData::build`.
+pub fn load_gltf(path: &Path) -> Result<GltfScene, gltf::Error> {
+    let (document, buffers, images) = gltf::import(path)?;
+
+    // the images are all there is to a texture, their samplers aren't kept
+    let textures = images.into_iter().map(convert_image).collect();
+
+    let materials = document
+        .materials()
+        .map(|material| {
+            let pbr = material.pbr_metallic_roughness();
+            Material {
+                name: material.name().map(str::to_string),
+                base_color: pbr.base_color_factor(),
+                base_color_texture: pbr
+                    .base_color_texture()
+                    .map(|info| info.texture().source().index()),
+            }
+        })
+        .collect();
+
+    // glTF meshes are made of primitives, each of which becomes a separate `MeshData`
+    let mut meshes = Vec::new();
+    let mut primitives = Vec::new();
+    for mesh in document.meshes() {
+        let mut indices = Vec::new();
+        for primitive in mesh.primitives() {
+            if primitive.mode() != gltf::mesh::Mode::Triangles {
+                continue;
+            }
+
+            let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+            let positions: Vec<[f32; 3]> = match reader.read_positions() {
+                Some(positions) => positions.collect(),
+                None => continue,
+            };
+
+            let mut data = MeshData {
+                name: mesh.name().unwrap_or_default().to_string(),
+                indices: match reader.read_indices() {
+                    Some(indices) => indices.into_u32().collect(),
+                    None => (0..positions.len() as u32).collect(),
+                },
+                normals: reader.read_normals().map_or_else(Vec::new, Iterator::collect),
+                tex_coords: reader
+                    .read_tex_coords(tex_coord_set(&primitive.material()))
+                    .map_or_else(|| vec![[0., 0.]; positions.len()], |t| t.into_f32().collect()),
+                material: primitive.material().index(),
+                positions,
+            };
+            if data.normals.is_empty() {
+                data.compute_normals();
+            }
+
+            indices.push(meshes.len());
+            meshes.push(data);
+        }
+        primitives.push(indices);
+    }
+
+    let nodes = document
+        .nodes()
+        .map(|node| SceneNode {
+            name: node.name().map(str::to_string),
+            transform: Matrix4::from(node.transform().matrix()),
+            meshes: node
+                .mesh()
+                .map_or_else(Vec::new, |mesh| primitives[mesh.index()].clone()),
+            children: node.children().map(|child| child.index()).collect(),
+        })
+        .collect();
+
+    let roots = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+        .map_or_else(Vec::new, |scene| scene.nodes().map(|node| node.index()).collect());
+
+    Ok(GltfScene {
+        meshes,
+        materials,
+        textures,
+        nodes,
+        roots,
+    })
+}
+
+/// Texture coordinate set the base color texture of `material` is sampled with.
+fn tex_coord_set(material: &gltf::Material) -> u32 {
+    material
+        .pbr_metallic_roughness()
+        .base_color_texture()
+        .map_or(0, |info| info.tex_coord())
+}
+
+fn convert_image(image: gltf::image::Data) -> TextureData {
+    use gltf::image::Format;
+
+    let pixels = match image.format {
+        Format::R8 => image.pixels.iter().flat_map(|&r| vec![r, r, r, 255]).collect(),
+        Format::R8G8 => image
+            .pixels
+            .chunks_exact(2)
+            .flat_map(|p| vec![p[0], p[1], 0, 255])
+            .collect(),
+        Format::R8G8B8 => image
+            .pixels
+            .chunks_exact(3)
+            .flat_map(|p| vec![p[0], p[1], p[2], 255])
+            .collect(),
+        Format::R8G8B8A8 => image.pixels,
+        // wider formats keep their most significant byte, floats are clamped
+        Format::R16 => image
+            .pixels
+            .chunks_exact(2)
+            .flat_map(|p| vec![p[1], p[1], p[1], 255])
+            .collect(),
+        Format::R16G16 => image
+            .pixels
+            .chunks_exact(4)
+            .flat_map(|p| vec![p[1], p[3], 0, 255])
+            .collect(),
+        Format::R16G16B16 => image
+            .pixels
+            .chunks_exact(6)
+            .flat_map(|p| vec![p[1], p[3], p[5], 255])
+            .collect(),
+        Format::R16G16B16A16 => image
+            .pixels
+            .chunks_exact(8)
+            .flat_map(|p| vec![p[1], p[3], p[5], p[7]])
+            .collect(),
+        Format::R32G32B32FLOAT | Format::R32G32B32A32FLOAT => {
+            let channels = if image.format == Format::R32G32B32FLOAT { 3 } else { 4 };
+            image
+                .pixels
+                .chunks_exact(4 * channels)
+                .flat_map(|p| {
+                    let mut pixel = vec![255; 4];
+                    for (c, value) in p.chunks_exact(4).enumerate() {
+                        let value = f32::from_le_bytes([value[0], value[1], value[2], value[3]]);
+                        pixel[c] = (value.clamp(0., 1.) * 255.).round() as u8;
+                    }
+                    pixel
+                })
+                .collect()
+        }
+    };
+
+    TextureData {
+        size: [image.width, image.height],
+        pixels,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Point3, Vector3};
+
+    use super::*;
+    use crate::Camera;
+
+    const TRIANGLE: [[f32; 3]; 3] = [[0., 0., 0.], [1., 0., 0.], [0., 1., 0.]];
+    /// `TRIANGLE` as a base64 data URI.
+    const EMBEDDED: &str = "data:application/octet-stream;base64,AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAA";
+
+    /// A tinted triangle scaled by a child node of a translated parent, its buffer at `uri`.
+    fn triangle_gltf(uri: &str) -> String {
+        format!(
+            r#"{{
+                "asset": {{ "version": "2.0" }},
+                "scene": 0,
+                "scenes": [{{ "nodes": [0] }}],
+                "nodes": [
+                    {{ "name": "parent", "translation": [1, 2, 3], "children": [1] }},
+                    {{ "name": "child", "scale": [2, 2, 2], "mesh": 0 }}
+                ],
+                "meshes": [{{ "primitives": [{{ "attributes": {{ "POSITION": 0 }}, "material": 0 }}] }}],
+                "materials": [{{ "pbrMetallicRoughness": {{ "baseColorFactor": [0.5, 0.25, 1, 1] }} }}],
+                "buffers": [{{ "byteLength": 36, "uri": "{}" }}],
+                "bufferViews": [{{ "buffer": 0, "byteLength": 36 }}],
+                "accessors": [{{
+                    "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                    "min": [0, 0, 0], "max": [1, 1, 0]
+                }}]
+            }}"#,
+            uri
+        )
+    }
+
+    #[test]
+    fn embedded_and_external_buffers_import_the_same_scene() {
+        let dir = std::env::temp_dir().join(format!("gltf-import-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let buffer: Vec<u8> = TRIANGLE.iter().flatten().flat_map(|c| c.to_le_bytes()).collect();
+        std::fs::write(dir.join("triangle.bin"), buffer).unwrap();
+
+        for uri in &[EMBEDDED, "triangle.bin"] {
+            let path = dir.join("triangle.gltf");
+            std::fs::write(&path, triangle_gltf(uri)).unwrap();
+            let gltf = load_gltf(&path).unwrap();
+
+            assert_eq!(gltf.meshes.len(), 1);
+            assert_eq!(gltf.meshes[0].positions, TRIANGLE);
+            assert_eq!(gltf.meshes[0].indices, [0, 1, 2]);
+            assert_eq!(gltf.meshes[0].normals, [[0., 0., 1.]; 3]);
+
+            let instances = gltf.to_scene().instances();
+            assert_eq!(instances.len(), 1);
+            let expected =
+                gltf_to_world() * Matrix4::new_translation(&Vector3::new(1., 2., 3.)) * Matrix4::new_scaling(2.);
+            assert_eq!(instances[0].model, expected);
+            assert_eq!(instances[0].color, [0.5, 0.25, 1., 1.]);
+            assert_eq!(instances[0].texture, None);
+        }
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn gltf_axes_keep_their_orientation_on_screen() {
+        // in front of the default camera, like the example spheres, and projected to normalized device coordinates
+        let transform = Camera::new(1.).get_transform() * Matrix4::new_translation(&Vector3::new(0., -6., 0.));
+        let project = |gltf: [f32; 3]| {
+            let world = gltf_to_world().transform_point(&Point3::from(gltf));
+            transform.transform_point(&world)
+        };
+
+        let center = project([0., 0., 0.]);
+        // `y` points down in Vulkan
+        assert!(project([1., 0., 0.]).x > center.x, "+X is to the left");
+        assert!(project([0., 1., 0.]).y < center.y, "+Y is down");
+        // the front of a model faces the camera
+        assert!(project([0., 0., 1.]).z < center.z, "+Z faces away");
+    }
+
+    #[test]
+    fn base_color_texture_uses_its_tex_coord_set() {
+        // positions followed by texture coordinate sets 0 and 1
+        let buffer = "data:application/octet-stream;base64,AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAAAAAAAAAAAAA\
+                      AAAAAAAAAAAAAAAAAAAAAAACAPgAAAD8AAEA/AAAAPwAAgD4AAIA/";
+        // a single red pixel
+        let image = "data:image/png;base64,iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR4nGP4z8DwHwAFAAH/iZk9HQAAAABJRU5ErkJggg==";
+        let gltf = format!(
+            r#"{{
+                "asset": {{ "version": "2.0" }},
+                "scenes": [{{ "nodes": [0] }}],
+                "nodes": [{{ "mesh": 0 }}],
+                "meshes": [{{
+                    "primitives": [{{
+                        "attributes": {{ "POSITION": 0, "TEXCOORD_0": 1, "TEXCOORD_1": 2 }},
+                        "material": 0
+                    }}]
+                }}],
+                "materials": [{{ "pbrMetallicRoughness": {{ "baseColorTexture": {{ "index": 0, "texCoord": 1 }} }} }}],
+                "textures": [{{ "source": 0 }}],
+                "images": [{{ "uri": "{}" }}],
+                "buffers": [{{ "byteLength": 84, "uri": "{}" }}],
+                "bufferViews": [
+                    {{ "buffer": 0, "byteLength": 36 }},
+                    {{ "buffer": 0, "byteOffset": 36, "byteLength": 24 }},
+                    {{ "buffer": 0, "byteOffset": 60, "byteLength": 24 }}
+                ],
+                "accessors": [
+                    {{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0, 0, 0], "max": [1, 1, 0] }},
+                    {{ "bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC2" }},
+                    {{ "bufferView": 2, "componentType": 5126, "count": 3, "type": "VEC2" }}
+                ]
+            }}"#,
+            image, buffer
+        );
+        let path = std::env::temp_dir().join(format!("gltf-tex-coords-{}.gltf", std::process::id()));
+        std::fs::write(&path, gltf).unwrap();
+        let gltf = load_gltf(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(gltf.meshes[0].tex_coords, [[0.25, 0.5], [0.75, 0.5], [0.25, 1.]]);
+        assert_eq!(gltf.textures[0].pixels, [255, 0, 0, 255]);
+        assert_eq!(gltf.to_scene().instances()[0].texture, Some(0));
+    }
+}
@@ -0,0 +1,291 @@
+use rendy::{
+    command::Families,
+    factory::Factory,
+    graph::{present::PresentNode, render::*, Graph, GraphBuilder, ImageId, NodeDesc, NodeId},
+    hal::{self, adapter::PhysicalDevice},
+    resource::Filter,
+    wsi::Surface,
+};
+
+use crate::{
+    pipelines::{
+        antialiasing::AntiAliasing,
+        bloom::build_bloom,
+        post_effect::{ParameterSource, PostEffect},
+        resolve::ResolveDesc,
+        shadow::{self, ShadowDesc},
+        *,
+    },
+    RenderSettings,
+};
+
+/// Nodes and images shared by the windowed and the headless graph.
+pub struct SceneNodes {
+    pub mesh_pass: NodeId,
+    /// Last pass of the post effect chain or the anti-aliasing after it, the one writing `color`.
+    pub posteffect_pass: NodeId,
+    pub color: ImageId,
+    pub hdr: ImageId,
+}
+
+/// Adds the mesh pass followed by the bloom, a pass for each of the effects and the anti-aliasing
+/// of `settings`, the final image ends up in `color`. Without any passes the image is copied over as is.
+///
+/// The mesh pass is preceded by a shadow pass rendering the depth of the scene as seen from the light
+/// into a shadow atlas, once for each cascade.
+/// With more than one sample the mesh pass renders to multisampled images, resolved into `hdr`.
+/// Temporal anti-aliasing blends `hdr` with the frames before and passes the result on.
+/// The last pass gets `ENCODE_SRGB` defined when `color_format` isn't an sRGB format.
+/// Passes reading the luminance buffer are preceded by a compute node measuring their source.
+pub fn build_scene<B: hal::Backend, T: MeshAux<B> + PostEffectAux + ?Sized + 'static>(
+    graph_builder: &mut GraphBuilder<B, T>,
+    size: [u32; 2],
+    color_format: hal::format::Format,
+    settings: &RenderSettings,
+) -> SceneNodes {
+    let window_kind = hal::image::Kind::D2(size[0], size[1], 1, 1);
+    let multisampled_kind = hal::image::Kind::D2(size[0], size[1], 1, settings.samples);
+    let multisampled = settings.samples > 1;
+
+    let color = graph_builder.create_image(
+        window_kind,
+        1,
+        color_format,
+        Some(hal::command::ClearValue {
+            color: hal::command::ClearColor {
+                float32: [0.1, 0.3, 0.4, 1.0],
+            },
+        }),
+    );
+
+    let (mesh_target, hdr) = create_mesh_target(
+        graph_builder,
+        size,
+        settings.samples,
+        hal::format::Format::Rgba32Sfloat,
+        hal::command::ClearColor {
+            float32: [0.1, 0.3, 0.4, 1.0],
+        },
+    );
+    // where every pixel moved since the last frame, for temporal anti-aliasing
+    let velocity_targets = if settings.antialiasing == AntiAliasing::Taa {
+        Some(create_mesh_target(
+            graph_builder,
+            size,
+            settings.samples,
+            hal::format::Format::Rg16Sfloat,
+            hal::command::ClearColor { float32: [0.; 4] },
+        ))
+    } else {
+        None
+    };
+
+    let depth = graph_builder.create_image(
+        multisampled_kind,
+        1,
+        hal::format::Format::D32Sfloat,
+        Some(hal::command::ClearValue {
+            depth_stencil: hal::command::ClearDepthStencil {
+                depth: 1.0,
+                stencil: 0,
+            },
+        }),
+    );
+
+    let atlas_size = shadow::atlas_size(settings.cascades);
+    let shadow_atlas = graph_builder.create_image(
+        hal::image::Kind::D2(atlas_size[0], atlas_size[1], 1, 1),
+        1,
+        hal::format::Format::D32Sfloat,
+        Some(hal::command::ClearValue {
+            depth_stencil: hal::command::ClearDepthStencil {
+                depth: 1.0,
+                stencil: 0,
+            },
+        }),
+    );
+    let shadow_pass = graph_builder.add_node(
+        ShadowDesc::new(settings.cascades)
+            .builder()
+            .into_subpass()
+            .with_depth_stencil(shadow_atlas)
+            .into_pass()
+    );
+
+    let mut subpass = mesh::PipelineDesc::new(settings)
+        .builder()
+        .with_image(shadow_atlas)
+        .with_dependency(shadow_pass)
+        .into_subpass()
+        .with_color(mesh_target);
+    if let Some((velocity_target, _)) = velocity_targets {
+        subpass = subpass.with_color(velocity_target);
+    }
+    let mesh_pass = graph_builder.add_node(subpass.with_depth_stencil(depth).into_pass());
+
+    // everything after the mesh pass waits for the resolves, if there are any
+    let resolves: Vec<_> = Some((mesh_target, hdr))
+        .into_iter()
+        .chain(velocity_targets)
+        .filter(|_| multisampled)
+        .map(|(target, resolved)| {
+            graph_builder.add_node(
+                ResolveDesc
+                    .builder()
+                    .with_image(target)
+                    .with_image(resolved)
+                    .with_dependency(mesh_pass)
+            )
+        })
+        .collect();
+    let (mut source, mut dependency) = (hdr, resolves.first().copied().unwrap_or(mesh_pass));
+
+    if let Some((_, velocity)) = velocity_targets {
+        let target = graph_builder.create_image(window_kind, 1, hal::format::Format::Rgba32Sfloat, None);
+        let mut builder = taa::TaaDesc::default()
+            .builder()
+            .with_image(hdr)
+            .with_image(velocity)
+            .with_image(target)
+            .with_dependency(mesh_pass);
+        for &resolve in &resolves {
+            builder = builder.with_dependency(resolve);
+        }
+        dependency = graph_builder.add_node(builder);
+        source = target;
+    }
+
+    // other anti-aliasing works on the tonemapped image, so it goes after the chain
+    let mut passes: Vec<_> = settings
+        .effects
+        .iter()
+        .enumerate()
+        .map(|(i, effect)| (effect.clone(), ParameterSource::Chain(i), Filter::Nearest))
+        .collect();
+    passes.extend(settings.antialiasing.effect().map(|effect| (effect, ParameterSource::None, Filter::Linear)));
+    if passes.is_empty() {
+        passes.push((PostEffect::new("passthrough.frag"), ParameterSource::None, Filter::Nearest));
+    }
+
+    // passes in between write to two images in turn, each reading what the one before wrote,
+    // `hdr` itself is left alone so it can be read back
+    let ping_pong: Vec<_> = (0..2.min(passes.len() - 1))
+        .map(|_| graph_builder.create_image(window_kind, 1, hal::format::Format::Rgba32Sfloat, None))
+        .collect();
+    let mut posteffect_pass = dependency;
+    if let Some(bloom) = &settings.bloom {
+        (source, posteffect_pass) = build_bloom(graph_builder, size, source, dependency, bloom);
+    }
+    let count = passes.len();
+    for (i, (mut effect, parameters, filter)) in passes.into_iter().enumerate() {
+        let last = i == count - 1;
+        let target = if last { color } else { ping_pong[i % 2] };
+
+        if last && color_format.base_format().1 != hal::format::ChannelType::Srgb {
+            effect = effect.with_define("ENCODE_SRGB", "1");
+        }
+
+        let desc = post_effect::PipelineDesc::new(&effect, parameters).with_filter(filter);
+        let reads_luminance = desc.reads_luminance();
+        let mut builder = desc.builder().with_image(source);
+        let mut dependency = posteffect_pass;
+        if reads_luminance {
+            let luminance = graph_builder.create_buffer(luminance::LUMINANCE_SIZE);
+            dependency = graph_builder.add_node(
+                luminance::LuminanceDesc::new(&effect.defines)
+                    .builder()
+                    .with_image(source)
+                    .with_buffer(luminance)
+                    .with_dependency(posteffect_pass)
+            );
+            builder = builder.with_buffer(luminance);
+        }
+
+        posteffect_pass = graph_builder.add_node(
+            builder
+                .into_subpass()
+                .with_dependency(dependency)
+                .with_color(target)
+                .into_pass()
+        );
+        source = target;
+    }
+
+    SceneNodes {
+        mesh_pass,
+        posteffect_pass,
+        color,
+        hdr,
+    }
+}
+
+/// Creates the image the mesh pass renders `clear` to, and the single sampled image it ends up in.
+/// They are the same image unless there is more than one sample.
+fn create_mesh_target<B: hal::Backend, T: ?Sized>(
+    graph_builder: &mut GraphBuilder<B, T>,
+    size: [u32; 2],
+    samples: hal::image::NumSamples,
+    format: hal::format::Format,
+    clear: hal::command::ClearColor,
+) -> (ImageId, ImageId) {
+    let clear = hal::command::ClearValue { color: clear };
+    let kind = hal::image::Kind::D2(size[0], size[1], 1, 1);
+    if samples == 1 {
+        let image = graph_builder.create_image(kind, 1, format, Some(clear));
+        return (image, image);
+    }
+
+    // the resolve overwrites all of it
+    let resolved = graph_builder.create_image(kind, 1, format, None);
+    let multisampled_kind = hal::image::Kind::D2(size[0], size[1], 1, samples);
+    (graph_builder.create_image(multisampled_kind, 1, format, Some(clear)), resolved)
+}
+
+/// Panics unless the device can render the mesh pass with `samples` samples.
+pub fn check_samples<B: hal::Backend>(factory: &Factory<B>, samples: hal::image::NumSamples) {
+    let limits = factory.physical().limits();
+    let supported = limits.framebuffer_color_sample_counts & limits.framebuffer_depth_sample_counts;
+    if !samples.is_power_of_two() || supported & samples == 0 {
+        let counts: Vec<_> = (0..8).map(|i| 1 << i).filter(|count| supported & count != 0).collect();
+        panic!("The device doesn't support {} samples, it supports {:?}.", samples, counts);
+    }
+}
+
+/// Panics unless the device can hold the shadow atlas of `cascades` cascades in a single image.
+pub fn check_cascades<B: hal::Backend>(factory: &Factory<B>, cascades: usize) {
+    let max_cascades = shadow::max_cascades(factory.physical().limits().max_image_2d_size);
+    if cascades > max_cascades {
+        let [width, height] = shadow::atlas_size(cascades);
+        panic!(
+            "The shadow atlas of {} cascades is {}x{}, the device supports at most {} cascades.",
+            cascades, width, height, max_cascades,
+        );
+    }
+}
+
+/// Builds the scene presenting to `surface`, with the settings of `aux`.
+pub fn build_graph<B: hal::Backend, T: MeshAux<B> + PostEffectAux + ?Sized + 'static>(
+    factory: &mut Factory<B>,
+    families: &mut Families<B>,
+    surface: Surface<B>,
+    size: [u32; 2],
+    aux: &T,
+) -> (Graph<B, T>, NodeId) {
+    let mut graph_builder = GraphBuilder::<B, T>::new();
+
+    check_samples(factory, aux.settings().samples);
+    check_cascades(factory, aux.settings().cascades);
+    let format = factory.get_surface_format(&surface);
+    let scene = build_scene(&mut graph_builder, size, format, aux.settings());
+
+    graph_builder.add_node(
+        PresentNode::builder(factory, surface, scene.color)
+            .with_dependency(scene.posteffect_pass)
+    );
+
+    let graph = graph_builder
+        .build(factory, families, aux)
+        .unwrap();
+
+    (graph, scene.mesh_pass)
+}
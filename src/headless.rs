@@ -0,0 +1,281 @@
+use rendy::{
+    command::{
+        CommandBuffer, CommandPool, ExecutableState, Family, MultiShot, PendingState,
+        SimultaneousUse, Submit, Transfer,
+    },
+    factory::{Config, Factory},
+    frame::Frames,
+    graph::{
+        gfx_acquire_barriers, gfx_release_barriers, GraphBuilder, GraphContext, ImageAccess, Node,
+        NodeBuffer, NodeBuildError, NodeDesc, NodeImage, NodeSubmittable,
+    },
+    hal,
+    init::AnyRendy,
+    memory::Download,
+    resource::{Buffer, BufferInfo, Escape},
+};
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    geometry::MeshData,
+    graph::{build_scene, check_cascades, check_samples},
+    texture::TextureData,
+    Aux, RenderSettings, Scene,
+};
+
+/// Bytes copied out of an image, filled in when the graph is disposed.
+pub type ReadbackTarget = Arc<Mutex<Option<Vec<u8>>>>;
+
+/// Copies its image into a host visible buffer every frame.
+#[derive(Debug)]
+pub struct ReadbackDesc {
+    pub target: ReadbackTarget,
+}
+
+#[derive(Debug)]
+pub struct Readback<B: hal::Backend> {
+    buffer: Escape<Buffer<B>>,
+    target: ReadbackTarget,
+    command_pool: CommandPool<B, Transfer>,
+    command_buffer:
+        CommandBuffer<B, Transfer, PendingState<ExecutableState<MultiShot<SimultaneousUse>>>>,
+    submit: Submit<B, SimultaneousUse>,
+}
+
+impl<'a, B> NodeSubmittable<'a, B> for Readback<B>
+where
+    B: hal::Backend,
+{
+    type Submittable = &'a Submit<B, SimultaneousUse>;
+    type Submittables = &'a [Submit<B, SimultaneousUse>];
+}
+
+impl<B, T> Node<B, T> for Readback<B>
+where
+    B: hal::Backend,
+    T: ?Sized,
+{
+    type Capability = Transfer;
+
+    fn run<'a>(
+        &'a mut self,
+        _ctx: &GraphContext<B>,
+        _factory: &Factory<B>,
+        _aux: &T,
+        _frames: &'a Frames<B>,
+    ) -> &'a [Submit<B, SimultaneousUse>] {
+        std::slice::from_ref(&self.submit)
+    }
+
+    unsafe fn dispose(mut self, factory: &mut Factory<B>, _aux: &T) {
+        self.command_pool
+            .free_buffers(Some(self.command_buffer.mark_complete()));
+        factory.destroy_command_pool(self.command_pool);
+
+        // the graph waits for the device to go idle before disposing nodes,
+        // so the copy from the last frame is complete at this point
+        let size = self.buffer.size();
+        let mut mapped = self.buffer.map(factory.device(), 0..size).unwrap();
+        let data: &[u8] = mapped.read(factory.device(), 0..size).unwrap();
+        *self.target.lock().unwrap() = Some(data.to_vec());
+    }
+}
+
+impl<B, T> NodeDesc<B, T> for ReadbackDesc
+where
+    B: hal::Backend,
+    T: ?Sized,
+{
+    type Node = Readback<B>;
+
+    fn images(&self) -> Vec<ImageAccess> {
+        vec![ImageAccess {
+            access: hal::image::Access::TRANSFER_READ,
+            usage: hal::image::Usage::TRANSFER_SRC,
+            layout: hal::image::Layout::TransferSrcOptimal,
+            stages: hal::pso::PipelineStage::TRANSFER,
+        }]
+    }
+
+    fn build<'a>(
+        self,
+        ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        family: &mut Family<B>,
+        _queue: usize,
+        _aux: &T,
+        buffers: Vec<NodeBuffer>,
+        images: Vec<NodeImage>,
+    ) -> Result<Self::Node, NodeBuildError> {
+        assert!(buffers.is_empty());
+        assert_eq!(images.len(), 1);
+
+        let image = ctx
+            .get_image(images[0].id)
+            .expect("No input image supplied.");
+        let extent = image.kind().extent();
+        let pixel_size = image.format().surface_desc().bits as u64 / 8;
+
+        let buffer = factory
+            .create_buffer(
+                BufferInfo {
+                    size: extent.width as u64 * extent.height as u64 * pixel_size,
+                    usage: hal::buffer::Usage::TRANSFER_DST,
+                },
+                Download,
+            )
+            .unwrap();
+
+        let mut command_pool = factory
+            .create_command_pool(family)
+            .map_err(NodeBuildError::OutOfMemory)?
+            .with_capability::<Transfer>()
+            .expect("Graph builder must provide family with Transfer capability");
+        let initial = command_pool.allocate_buffers(1).remove(0);
+        let mut recording = initial.begin(MultiShot(SimultaneousUse), ());
+
+        unsafe {
+            {
+                let (stages, barriers) = gfx_acquire_barriers(ctx, None, &images);
+                recording
+                    .encoder()
+                    .pipeline_barrier(stages, hal::memory::Dependencies::empty(), barriers);
+            }
+
+            hal::command::CommandBuffer::copy_image_to_buffer(
+                recording.raw(),
+                image.raw(),
+                images[0].layout,
+                buffer.raw(),
+                Some(hal::command::BufferImageCopy {
+                    buffer_offset: 0,
+                    buffer_width: 0,
+                    buffer_height: 0,
+                    image_layers: hal::image::SubresourceLayers {
+                        aspects: hal::format::Aspects::COLOR,
+                        level: 0,
+                        layers: 0..1,
+                    },
+                    image_offset: hal::image::Offset::ZERO,
+                    image_extent: extent,
+                }),
+            );
+
+            {
+                let (mut stages, mut barriers) = gfx_release_barriers(ctx, None, &images);
+                stages.start |= hal::pso::PipelineStage::TRANSFER;
+                stages.end |= hal::pso::PipelineStage::HOST;
+                barriers.push(hal::memory::Barrier::Buffer {
+                    states: hal::buffer::Access::TRANSFER_WRITE..hal::buffer::Access::HOST_READ,
+                    families: None,
+                    target: buffer.raw(),
+                    range: None..None,
+                });
+                recording
+                    .encoder()
+                    .pipeline_barrier(stages, hal::memory::Dependencies::empty(), barriers);
+            }
+        }
+
+        let (submit, command_buffer) = recording.finish().submit();
+
+        Ok(Readback {
+            buffer,
+            target: self.target,
+            command_pool,
+            command_buffer,
+            submit,
+        })
+    }
+}
+
+/// Writes tightly packed `Rgba8` pixels as a PNG file.
+pub fn write_png(path: &Path, size: [u32; 2], data: &[u8]) -> std::io::Result<()> {
+    image::save_buffer(path, data, size[0], size[1], image::ColorType::RGBA(8))
+}
+
+/// Writes tightly packed `Rgba32Sfloat` pixels as a color PFM file, dropping alpha.
+pub fn write_pfm(path: &Path, size: [u32; 2], data: &[u8]) -> std::io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    // negative scale marks little endian data
+    write!(file, "PF\n{} {}\n-1.0\n", size[0], size[1])?;
+
+    let row_size = size[0] as usize * 16;
+    // pfm stores rows bottom to top
+    for row in data.chunks_exact(row_size).rev() {
+        for pixel in row.chunks_exact(16) {
+            file.write_all(&pixel[..12])?;
+        }
+    }
+    file.flush()
+}
+
+/// Renders a single frame of `scene` without a window and writes the final image to `output`,
+/// the `hdr` image is written next to it with a `.pfm` extension.
+pub fn run(
+    size: [u32; 2],
+    scene: &Scene,
+    meshes: &[MeshData],
+    textures: &[TextureData],
+    settings: &RenderSettings,
+    output: &Path,
+) {
+    let config: Config = Default::default();
+
+    let rendy = AnyRendy::init_auto(&config).unwrap();
+
+    rendy::with_any_rendy!((rendy)
+        (mut factory, mut families) => {
+
+        let mut aux = Aux::new(size);
+        aux.scene = scene.clone();
+        aux.settings = settings.clone();
+
+        let mut graph_builder = GraphBuilder::new();
+        check_samples(&factory, settings.samples);
+        check_cascades(&factory, settings.cascades);
+        let scene = build_scene(&mut graph_builder, size, hal::format::Format::Rgba8Srgb, settings);
+
+        let color_target = ReadbackTarget::default();
+        let hdr_target = ReadbackTarget::default();
+
+        graph_builder.add_node(
+            ReadbackDesc { target: color_target.clone() }
+                .builder()
+                .with_image(scene.color)
+                .with_dependency(scene.posteffect_pass)
+        );
+        graph_builder.add_node(
+            ReadbackDesc { target: hdr_target.clone() }
+                .builder()
+                .with_image(scene.hdr)
+                .with_dependency(scene.posteffect_pass)
+        );
+
+        let mut graph = graph_builder
+            .build(&mut factory, &mut families, &aux)
+            .unwrap();
+
+        let queue = graph.node_queue(scene.mesh_pass);
+        aux.meshes = meshes.iter().map(|mesh| mesh.build(&factory, queue)).collect();
+        aux.textures = textures.iter().map(|texture| texture.build(&mut factory, queue)).collect();
+        factory.maintain(&mut families);
+
+        graph.run(&mut factory, &mut families, &aux);
+        graph.dispose(&mut factory, &aux);
+        aux.meshes.clear();
+        aux.textures.clear();
+
+        let color = color_target.lock().unwrap().take().expect("Color image was not read back.");
+        write_png(output, size, &color).expect("Couldn't write color image.");
+
+        let hdr = hdr_target.lock().unwrap().take().expect("Hdr image was not read back.");
+        write_pfm(&output.with_extension("pfm"), size, &hdr).expect("Couldn't write hdr image.");
+    })
+}
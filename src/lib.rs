@@ -0,0 +1,84 @@
+pub mod camera;
+pub mod geometry;
+pub mod gltf_import;
+pub mod graph;
+pub mod headless;
+pub mod light;
+pub mod pipelines;
+pub mod reference;
+pub mod scene;
+pub mod shaders;
+pub mod texture;
+
+pub use camera::Camera;
+pub use light::DirectionalLight;
+pub use scene::Scene;
+
+use rendy::{hal, mesh::Mesh, texture::Texture};
+
+use pipelines::{antialiasing::AntiAliasing, bloom::Bloom, post_effect::PostEffect};
+
+/// How the scene is rendered, shared by the graph and the reference renderer.
+#[derive(Clone, Debug)]
+pub struct RenderSettings {
+    /// Samples per pixel of the mesh pass, resolved before anything reads it.
+    /// Changing it needs a rebuild of the graph.
+    pub samples: hal::image::NumSamples,
+    /// Bloom before the post effect chain, turning it on or off needs a rebuild of the graph
+    /// while the threshold and intensity are picked up every frame.
+    pub bloom: Option<Bloom>,
+    /// Post effect chain, changing the shaders or defines needs a rebuild of the graph
+    /// while parameters are picked up every frame.
+    pub effects: Vec<PostEffect>,
+    /// Anti-aliasing after the post effect chain, changing it needs a rebuild of the graph.
+    pub antialiasing: AntiAliasing,
+    /// Shadow cascades splitting what the camera sees, each with its own part of the shadow atlas.
+    /// Changing it needs a rebuild of the graph.
+    pub cascades: usize,
+    /// Tints everything by the cascade its shadow comes from, changing it needs a rebuild of the graph.
+    pub debug_cascades: bool,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            samples: 1,
+            bloom: None,
+            effects: Vec::new(),
+            antialiasing: AntiAliasing::None,
+            cascades: 4,
+            debug_cascades: false,
+        }
+    }
+}
+
+/// Data shared with every node of the graph.
+pub struct Aux<B: hal::Backend> {
+    pub meshes: Vec<Mesh<B>>,
+    /// Albedo textures the objects of the scene refer to.
+    pub textures: Vec<Texture<B>>,
+    /// Objects drawn every frame, referring to `meshes` by index.
+    pub scene: Scene,
+    pub camera: Camera,
+    /// Light shading the scene and casting its shadows.
+    pub light: DirectionalLight,
+    pub settings: RenderSettings,
+    /// Seconds since the last frame, zero before the first one.
+    pub delta_time: f32,
+    pub size: [u32; 2],
+}
+
+impl<B: hal::Backend> Aux<B> {
+    pub fn new(size: [u32; 2]) -> Self {
+        Self {
+            meshes: Vec::new(),
+            textures: Vec::new(),
+            scene: Scene::default(),
+            camera: Camera::new(size[0] as f32 / size[1] as f32),
+            light: DirectionalLight::default(),
+            settings: RenderSettings::default(),
+            delta_time: 0.,
+            size,
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use nalgebra::{Matrix4, Point3, Vector3};
+
+use crate::{pipelines::shadow::SHADOW_MAP_SIZE, Camera};
+
+/// How far the splits between cascades are from uniform towards logarithmic, which gives
+/// the nearer cascades more of the resolution.
+const CASCADE_SPLIT_LAMBDA: f32 = 0.75;
+
+/// Where the cascades between `near` and `far` begin and end, from `0` at the near plane
+/// to `1` at the far plane, one more than there are cascades.
+fn cascade_splits(near: f32, far: f32, cascades: usize) -> Vec<f32> {
+    (0..=cascades)
+        .map(|i| {
+            let t = i as f32 / cascades as f32;
+            let logarithmic = near * (far / near).powf(t);
+            let uniform = near + (far - near) * t;
+            let distance = logarithmic * CASCADE_SPLIT_LAMBDA + uniform * (1. - CASCADE_SPLIT_LAMBDA);
+            (distance - near) / (far - near)
+        })
+        .collect()
+}
+
+/// Light arriving from infinitely far away, like the sun.
+#[derive(Clone, Copy, Debug)]
+pub struct DirectionalLight {
+    /// Direction the light travels in, doesn't need to be unit length.
+    pub direction: Vector3<f32>,
+    /// Linear color, scaled by the intensity.
+    pub color: Vector3<f32>,
+}
+
+impl Default for DirectionalLight {
+    fn default() -> Self {
+        Self {
+            direction: Vector3::new(-1.2, -1., -0.6),
+            color: Vector3::new(1., 1., 1.),
+        }
+    }
+}
+
+impl DirectionalLight {
+    /// Transforms into the clip space of the shadow map of each of `cascades` cascades,
+    /// splitting what `camera` sees between its near and far plane from the nearest on.
+    pub fn get_cascade_transforms(&self, camera: &Camera, cascades: usize) -> Vec<Matrix4<f32>> {
+        assert!(cascades > 0, "There must be at least one cascade.");
+        cascade_splits(camera.near, camera.far, cascades)
+            .windows(2)
+            .map(|split| {
+                let (center, radius) = camera.get_bounding_sphere(split[0]..split[1]);
+                self.get_transform(&center, radius)
+            })
+            .collect()
+    }
+
+    /// Transform into the clip space of a shadow map looking along the light and covering the sphere
+    /// at `center` with `radius`, with depths from `0` to `1` across it.
+    fn get_transform(&self, center: &Point3<f32>, radius: f32) -> Matrix4<f32> {
+        let direction = self.direction.normalize();
+        // any up vector works as long as it isn't parallel to the light
+        let up = if direction.z.abs() < 0.99 {
+            Vector3::z()
+        } else {
+            Vector3::y()
+        };
+        let rotation = Matrix4::look_at_lh(&Point3::origin(), &Point3::from(direction), &up);
+
+        // moving the shadow map by whole texels keeps its edges from shimmering as the camera moves
+        let texel = 2. * radius / SHADOW_MAP_SIZE as f32;
+        let mut center = rotation.transform_point(center);
+        center.x = (center.x / texel).round() * texel;
+        center.y = (center.y / texel).round() * texel;
+
+        let view = Matrix4::new_translation(&-Vector3::new(center.x, center.y, center.z - radius)) * rotation;
+        let projection = Matrix4::new_nonuniform_scaling(&Vector3::new(1. / radius, 1. / radius, 0.5 / radius));
+        projection * view
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cascades_split_between_uniform_and_logarithmic() {
+        assert_eq!(cascade_splits(0.1, 100., 1), vec![0., 1.]);
+
+        // 0.75 * 0.1 * 1000^0.5 + 0.25 * 50.05 from the near plane on
+        let halves = cascade_splits(0.1, 100., 2);
+        assert!((halves[1] - 0.148).abs() < 1e-3, "{:?}", halves);
+
+        let splits = cascade_splits(0.1, 100., 4);
+        assert_eq!(splits.len(), 5);
+        assert_eq!(splits[0], 0.);
+        assert!((splits[4] - 1.).abs() < 1e-6, "{:?}", splits);
+        for (i, split) in splits.windows(2).enumerate() {
+            assert!(split[0] < split[1], "{:?}", splits);
+            // nearer cascades cover less than their uniform share
+            assert!(split[1] < (i + 1) as f32 / 4. || i == 3, "{:?}", splits);
+        }
+    }
+}
@@ -1,36 +1,34 @@
-#![allow(warnings)]
-
-
-pub mod pipelines;
-
-use pipelines::*;
-use genmesh::generators::{IndexedPolygon, SharedVertex};
-use nalgebra::*;
 use rendy::{
-    command::{Families, QueueId, RenderPassEncoder},
-    factory::{Config, Factory, ImageState},
-    graph::{
-        present::PresentNode, render::*, Graph, GraphBuilder, GraphContext, NodeBuffer, NodeImage,
-    },
-    hal::{self, adapter::PhysicalDevice, pso::ShaderStageFlags},
+    command::Families,
+    factory::{Config, Factory},
+    graph::Graph,
+    hal,
     init::winit::{
         self,
-        dpi::{Size,PhysicalSize},
+        dpi::{PhysicalSize, Size},
         event::{DeviceEvent, Event, WindowEvent},
         event_loop::{ControlFlow, EventLoop},
         window::WindowBuilder,
     },
-    init::AnyWindowedRendy,
-    memory::Dynamic,
-    mesh::{AsVertex, Mesh, Position},
-    resource::{Buffer, BufferInfo, DescriptorSet, DescriptorSetLayout, Escape, Handle},
-    shader::{
-        ShaderKind, ShaderSet, ShaderSetBuilder, SourceLanguage, SourceShaderInfo, SpirvReflection,
-        SpirvShader,
+};
+use std::{path::PathBuf, time::Instant};
+use testing::{
+    geometry::{self, MeshData},
+    gltf_import,
+    graph::build_graph,
+    headless,
+    pipelines::{
+        antialiasing::AntiAliasing,
+        bloom::Bloom,
+        post_effect::{ParameterValue, PostEffect},
+        taa,
     },
-    texture::{image::ImageTextureConfig, Texture},
+    reference,
+    scene::{self, Scene},
+    shaders::{self, ShaderWatcher},
+    texture::TextureData,
+    Aux, Camera, DirectionalLight, RenderSettings,
 };
-use std::{fs::read_to_string, time::Instant};
 
 enum Direction {
     Left = 0,
@@ -41,60 +39,27 @@ enum Direction {
     Backward = 5,
 }
 
-#[derive(Clone, Copy)]
-pub struct Camera {
-    pitch: f32,
-    yaw: f32,
-    pos: Point3<f32>,
-    aspect: f32,
-    fov: f32,
-    near: f32,
-    far: f32,
-}
-
-impl Default for Camera {
-    fn default() -> Self {
-        Self {
-            pitch: 0.,
-            yaw: 0.,
-            pos: Point3::new(0., 0., 0.),
-            aspect: 1.,
-            fov: 45.,
-            near: 0.1,
-            far: 1.,
-        }
+/// Replaces the graph, picking up the current size and shaders.
+fn rebuild_graph<B: hal::Backend>(
+    graph: &mut Option<Graph<B, Aux<B>>>,
+    factory: &mut Factory<B>,
+    families: &mut Families<B>,
+    window: &winit::window::Window,
+    aux: &Aux<B>,
+) {
+    // the old swapchain and the size dependent images go away with the graph,
+    // meshes and textures are owned by aux and survive the rebuild
+    if let Some(graph) = graph.take() {
+        graph.dispose(factory, aux);
     }
-}
 
-impl Camera {
-    pub fn get_view_direction(&self) -> Vector3<f32> {
-        Vector3::new(
-            self.pitch.cos() * self.yaw.sin(),
-            self.pitch.cos() * self.yaw.cos(),
-            self.pitch.sin(),
-        )
-    }
-    pub fn get_view(&self) -> Matrix4<f32> {
-        Matrix4::look_at_lh(
-            &self.pos,
-            &Point3::from(self.get_view_direction()+self.pos.coords),
-            &Vector3::new(0., 0., 1.),
-        )
+    // minimized windows report a zero size, wait for a usable one
+    if aux.size[0] == 0 || aux.size[1] == 0 {
+        return;
     }
-    pub fn get_projection(&self) -> Matrix4<f32> {
-        Matrix4::new_perspective(self.aspect, self.fov, self.near, self.far)
-    }
-    pub fn get_transform(&self) -> Matrix4<f32> {
-        self.get_projection() * self.get_view()
-    }
-}
 
-pub struct Aux<B: hal::Backend> {
-    pub mesh: Option<rendy::mesh::Mesh<B>>,
-    pub camera: Camera,
-    pub size: [u32; 2],
-    pub keys: [bool; 6],
-    pub last_update: Instant,
+    let surface = factory.create_surface(window).unwrap();
+    *graph = Some(build_graph(factory, families, surface, aux.size, aux).0);
 }
 
 fn run<B: hal::Backend>(
@@ -106,48 +71,84 @@ fn run<B: hal::Backend>(
     mut aux: Aux<B>,
 ) {
     let mut graph = Some(graph);
+    let mut keys = [false; 6];
+    let mut last_update = Instant::now();
+    let mut frame = 0;
+    // embedded shaders can't change, and the assets directory may not even exist
+    let watcher = if shaders::EMBEDDED {
+        None
+    } else {
+        Some(ShaderWatcher::new().expect("Couldn't watch the assets directory."))
+    };
     event_loop.run(move |event, _, control_flow| {
         *control_flow = ControlFlow::Poll;
         match event {
             Event::WindowEvent { event, .. } => match event {
                 WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
-                WindowEvent::Resized(_size) => {}
+                WindowEvent::Resized(size) => {
+                    if aux.size == [size.width, size.height] {
+                        return;
+                    }
+                    aux.size = [size.width, size.height];
+                    if size.height != 0 {
+                        aux.camera.aspect = size.width as f32 / size.height as f32;
+                    }
+
+                    rebuild_graph(&mut graph, &mut factory, &mut families, &window, &aux);
+                }
                 WindowEvent::KeyboardInput { input, .. } => {
                     use rendy::init::winit::event::VirtualKeyCode::*;
                     let pressed = input.state == winit::event::ElementState::Pressed;
                     match input.virtual_keycode {
-                        Some(W) => aux.keys[Direction::Forward as usize] = pressed,
-                        Some(S) => aux.keys[Direction::Backward as usize] = pressed,
-                        Some(A) => aux.keys[Direction::Left as usize] = pressed,
-                        Some(D) => aux.keys[Direction::Right as usize] = pressed,
-                        Some(Space) => aux.keys[Direction::Up as usize] = pressed,
-                        Some(LShift) => aux.keys[Direction::Down as usize] = pressed,
+                        Some(W) => keys[Direction::Forward as usize] = pressed,
+                        Some(S) => keys[Direction::Backward as usize] = pressed,
+                        Some(A) => keys[Direction::Left as usize] = pressed,
+                        Some(D) => keys[Direction::Right as usize] = pressed,
+                        Some(Space) => keys[Direction::Up as usize] = pressed,
+                        Some(LShift) => keys[Direction::Down as usize] = pressed,
                         _ => {}
                     }
                 }
                 _ => {}
             },
-            Event::DeviceEvent { event, .. } => match event {
-                DeviceEvent::MouseMotion { delta } => {
-                    aux.camera.yaw += (delta.0 * 0.005) as f32;
-                    aux.camera.pitch -= (delta.1 * 0.005) as f32;
-                    aux.camera.pitch = aux.camera.pitch.min(1.57).max(-1.57);
-                }
-                _ => {}
-            },
+            Event::DeviceEvent {
+                event: DeviceEvent::MouseMotion { delta },
+                ..
+            } => {
+                aux.camera.yaw += (delta.0 * 0.005) as f32;
+                aux.camera.pitch -= (delta.1 * 0.005) as f32;
+                aux.camera.pitch = aux.camera.pitch.clamp(-1.57, 1.57);
+            }
             Event::MainEventsCleared => {
+                // a shader that doesn't compile leaves the running graph alone
+                let changed = watcher.as_ref().map_or_else(Vec::new, ShaderWatcher::changed);
+                if !changed.is_empty() {
+                    match shaders::reload(&changed) {
+                        Ok(true) => {
+                            rebuild_graph(&mut graph, &mut factory, &mut families, &window, &aux);
+                        }
+                        Ok(false) => {}
+                        Err(e) => eprintln!("{}", e),
+                    }
+                }
+
                 window.request_redraw();
                 factory.maintain(&mut families);
             }
             Event::RedrawRequested(_) => {
+                let delta = last_update.elapsed().as_secs_f32();
+                println!("FPS: {}", 1. / delta);
+                last_update = Instant::now();
+                aux.delta_time = delta;
+                if aux.settings.antialiasing == AntiAliasing::Taa {
+                    aux.camera.jitter = taa::jitter(frame, aux.size);
+                }
+                frame += 1;
+
                 if let Some(ref mut graph) = graph {
                     graph.run(&mut factory, &mut families, &aux);
                 }
 
-                let delta = aux.last_update.elapsed().as_secs_f32();
-                println!("FPS: {}", 1. / delta);
-                aux.last_update = Instant::now();
-
                 let speed = delta * 2.;
 
                 let forward_vec = aux.camera.get_view_direction().normalize();
@@ -155,41 +156,189 @@ fn run<B: hal::Backend>(
                     .cross(&nalgebra::Vector3::new(0., 0., 1.))
                     .normalize();
 
-                if aux.keys[Direction::Forward as usize] {
+                if keys[Direction::Forward as usize] {
                     aux.camera.pos -= forward_vec * speed;
                 }
-                if aux.keys[Direction::Backward as usize] {
+                if keys[Direction::Backward as usize] {
                     aux.camera.pos += forward_vec * speed;
                 }
-                if aux.keys[Direction::Right as usize] {
+                if keys[Direction::Right as usize] {
                     aux.camera.pos -= sideways_vec * speed;
                 }
-                if aux.keys[Direction::Left as usize] {
+                if keys[Direction::Left as usize] {
                     aux.camera.pos += sideways_vec * speed;
                 }
-                if aux.keys[Direction::Down as usize] {
+                if keys[Direction::Down as usize] {
                     aux.camera.pos.z += speed;
                 }
-                if aux.keys[Direction::Up as usize] {
+                if keys[Direction::Up as usize] {
                     aux.camera.pos.z -= speed;
                 }
             }
             _ => {}
         }
 
-        if *control_flow == ControlFlow::Exit && graph.is_some() {
-            graph.take().unwrap().dispose(&mut factory, &aux);
-            drop(aux.mesh.take());
+        if *control_flow == ControlFlow::Exit {
+            if let Some(graph) = graph.take() {
+                graph.dispose(&mut factory, &aux);
+            }
+            aux.meshes.clear();
+            aux.textures.clear();
         }
     })
 }
 
+/// `<shader>[:<name>=<value>,...]`, the optional part lists defines.
+fn parse_post_effect(arg: &str) -> PostEffect {
+    let (shader, defines) = arg.split_once(':').unwrap_or((arg, ""));
+    defines
+        .split(',')
+        .filter(|define| !define.is_empty())
+        .fold(PostEffect::new(shader), |effect, define| {
+            let (name, value) = define.split_once('=').unwrap_or((define, "1"));
+            effect.with_define(name, value)
+        })
+}
+
+/// `<name>=<x>[,<y>[,<z>[,<w>]]]`, the number of components picks the type.
+fn parse_parameter(arg: &str) -> (&str, ParameterValue) {
+    let (name, value) = arg.split_once('=').expect("--param expects <name>=<value>.");
+    let components: Vec<f32> = value
+        .split(',')
+        .map(|c| c.parse().expect("Invalid parameter value."))
+        .collect();
+    let value = ParameterValue::from_components(&components).expect("Parameters have at most 4 components.");
+    (name, value)
+}
+
 fn main() {
+    let mut headless = None;
+    let mut reference = None;
+    let mut obj = None;
+    let mut gltf = None;
+    let mut texture = None;
+    let mut size = [512, 512];
+    let mut settings = RenderSettings::default();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--headless" => {
+                headless = Some(PathBuf::from(args.next().expect("--headless expects an output path.")));
+            }
+            "--reference" => {
+                reference = Some(PathBuf::from(args.next().expect("--reference expects an output path.")));
+            }
+            "--obj" => {
+                obj = Some(PathBuf::from(args.next().expect("--obj expects a path to an OBJ file.")));
+            }
+            "--gltf" => {
+                gltf = Some(PathBuf::from(args.next().expect("--gltf expects a path to a glTF file.")));
+            }
+            "--texture" => {
+                texture = Some(PathBuf::from(args.next().expect("--texture expects a path to an image.")));
+            }
+            "--post" => {
+                settings.effects.push(parse_post_effect(&args.next().expect("--post expects a fragment shader.")));
+            }
+            "--param" => {
+                let arg = args.next().expect("--param expects <name>=<value>.");
+                let (name, value) = parse_parameter(&arg);
+                settings
+                    .effects
+                    .last_mut()
+                    .expect("--param applies to the last --post.")
+                    .set_parameter(name, value);
+            }
+            "--bloom" => {
+                settings.bloom.get_or_insert_with(Bloom::default);
+            }
+            "--bloom-threshold" => {
+                let value = args.next().expect("--bloom-threshold expects a number.");
+                settings.bloom.get_or_insert_with(Bloom::default).threshold = value.parse().expect("Invalid threshold.");
+            }
+            "--bloom-intensity" => {
+                let value = args.next().expect("--bloom-intensity expects a number.");
+                settings.bloom.get_or_insert_with(Bloom::default).intensity = value.parse().expect("Invalid intensity.");
+            }
+            "--samples" => {
+                let value = args.next().expect("--samples expects a number.");
+                settings.samples = value.parse().expect("Invalid sample count.");
+            }
+            "--aa" => {
+                let value = args.next().expect("--aa expects none, fxaa or taa.");
+                settings.antialiasing = value.parse().unwrap_or_else(|e| panic!("{}", e));
+            }
+            "--cascades" => {
+                let value = args.next().expect("--cascades expects a number.");
+                settings.cascades = value.parse().ok().filter(|&cascades| cascades > 0).expect("Invalid cascade count.");
+            }
+            "--debug-cascades" => {
+                settings.debug_cascades = true;
+            }
+            "--size" => {
+                let value = args.next().expect("--size expects <width>x<height>.");
+                let mut dims = value.split('x').map(|d| d.parse::<u32>().expect("Invalid size."));
+                size = [dims.next().unwrap(), dims.next().expect("--size expects <width>x<height>.")];
+            }
+            _ => panic!("Unknown argument {}", arg),
+        }
+    }
+
+    let (meshes, mut textures, mut scene): (Vec<MeshData>, Vec<TextureData>, Scene) = match (obj, gltf) {
+        (Some(_), Some(_)) => panic!("--obj and --gltf can't be used together."),
+        (Some(path), None) => {
+            let meshes = geometry::load_obj(&path).expect("Couldn't load OBJ file.");
+            let scene = Scene::from_meshes(meshes.iter().map(|mesh| mesh.name.clone()));
+            (meshes, Vec::new(), scene)
+        }
+        (None, Some(path)) => {
+            let gltf = gltf_import::load_gltf(&path).expect("Couldn't load glTF file.");
+            let scene = gltf.to_scene();
+            (gltf.meshes, gltf.textures, scene)
+        }
+        (None, None) => (vec![geometry::icosphere()], Vec::new(), scene::spheres(3)),
+    };
+
+    // the chain the viewer always had, tonemapped first
+    if settings.effects.is_empty() {
+        settings.effects.push(PostEffect::new("tonemap.frag").with_define("AUTO_EXPOSURE", "1"));
+        settings.effects.push(
+            PostEffect::new("posteffect.frag")
+                .with_parameter("frequency", 0.05)
+                .with_parameter("amplitude", 0.05),
+        );
+    }
+
+    // the texture applies to every object that doesn't come with its own
+    if let Some(path) = texture {
+        textures.push(TextureData::load(&path).expect("Couldn't load texture."));
+        for object in &mut scene.objects {
+            if object.mesh.is_some() && object.texture.is_none() {
+                object.texture = Some(textures.len() - 1);
+            }
+        }
+    }
+
+    if let Some(output) = reference {
+        let camera = Camera::new(size[0] as f32 / size[1] as f32);
+        let light = DirectionalLight::default();
+        let frame = reference::render(&camera, &light, &scene, &meshes, &textures, &settings, size);
+        headless::write_png(&output, size, &frame.color.to_srgb8()).expect("Couldn't write color image.");
+        headless::write_pfm(&output.with_extension("pfm"), size, &frame.hdr.to_bytes()).expect("Couldn't write hdr image.");
+        return;
+    }
+
+    if let Some(output) = headless {
+        headless::run(size, &scene, &meshes, &textures, &settings, &output);
+        return;
+    }
+
     let event_loop = EventLoop::new();
 
     let window = WindowBuilder::new()
         .with_title("Hello, triangle!")
-        .with_inner_size(Size::new(PhysicalSize::new(512, 512)));
+        .with_inner_size(Size::new(PhysicalSize::new(size[0], size[1])));
 
     let config: Config = Default::default();
 
@@ -199,101 +348,15 @@ fn main() {
         use back; (mut factory, mut families, surface, window) => {
 
         let size = window.inner_size();
-        let mut aux = Aux{
-                mesh: None,
-                camera: Camera {
-                    aspect: size.width as f32 / size.height as f32,
-                    far: 20.,
-                    ..Default::default()
-                },
-                size: [size.width, size.height],
-                keys: [false; 6],
-                last_update: Instant::now()
-        };
-
-        let mut graph_builder = GraphBuilder::<_, Aux<_>>::new();
-
-        let window_kind = hal::image::Kind::D2(size.width as u32, size.height as u32, 1, 1);
-
-        let color = graph_builder.create_image(
-            window_kind,
-            1,
-            factory.get_surface_format(&surface),
-            Some(hal::command::ClearValue {
-                color: hal::command::ClearColor {
-                    float32: [0.1, 0.3, 0.4, 1.0],
-                },
-            }),
-        );
-        
-        let hdr = graph_builder.create_image(
-            window_kind,
-            1,
-            hal::format::Format::Rgba32Sfloat,
-            Some(hal::command::ClearValue {
-                color: hal::command::ClearColor {
-                    float32: [0.1, 0.3, 0.4, 1.0],
-                },
-            }),
-        );
-
-        let depth = graph_builder.create_image(
-            window_kind,
-            1,
-            hal::format::Format::D32Sfloat,
-            Some(hal::command::ClearValue {
-                depth_stencil: hal::command::ClearDepthStencil {
-                    depth: 1.0,
-                    stencil: 0,
-                },
-            }),
-        );
-
-        let mesh_pass = graph_builder.add_node(
-            mesh::Pipeline::builder()
-                .into_subpass()
-                .with_color(hdr)
-                .with_depth_stencil(depth)
-                .into_pass()
-        );
-
-        let posteffect_pass = graph_builder.add_node(
-            post_effect::Pipeline::builder()
-                .with_image(hdr)
-                .into_subpass()
-                .with_dependency(mesh_pass)
-                .with_color(color)
-                .into_pass()
-        );
-
-        graph_builder.add_node(
-            PresentNode::builder(&factory, surface, color)
-                .with_dependency(posteffect_pass)
-        );
-
-        let graph = graph_builder
-            .build(&mut factory, &mut families, &aux)
-            .unwrap();
+        let mut aux = Aux::<back::Backend>::new([size.width, size.height]);
+        aux.scene = scene;
+        aux.settings = settings;
 
-        aux.mesh = {
-            let icosphere = genmesh::generators::IcoSphere::subdivide(4);
-            let indices: Vec<_> = genmesh::Vertices::vertices(icosphere.indexed_polygon_iter())
-                .map(|i| i as u32)
-                .collect();
-            let vertices: Vec<_> = icosphere
-                .shared_vertex_iter()
-                .map(|v| Position(
-                    v.pos.into()
-                ))
-                .collect();
-            let mesh = Mesh::<back::Backend>::builder()
-            .with_indices(&indices[..])
-            .with_vertices(&vertices[..])
-            .build(graph.node_queue(mesh_pass), &factory)
-            .unwrap();
+        let (graph, mesh_pass) = build_graph(&mut factory, &mut families, surface, aux.size, &aux);
 
-            Some(mesh)
-        };
+        let queue = graph.node_queue(mesh_pass);
+        aux.meshes = meshes.iter().map(|mesh| mesh.build(&factory, queue)).collect();
+        aux.textures = textures.iter().map(|texture| texture.build(&mut factory, queue)).collect();
 
         // no autocompletion in macros so this is what you get
         run(event_loop, window, graph, factory, families, aux);
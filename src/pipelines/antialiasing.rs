@@ -0,0 +1,52 @@
+use std::{fmt, str::FromStr};
+
+use super::post_effect::PostEffect;
+
+/// Anti-aliasing of the tonemapped image, after the post effect chain and before present,
+/// unless noted otherwise.
+///
+/// Changing it needs a rebuild of the graph.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AntiAliasing {
+    #[default]
+    None,
+    /// `fxaa.frag`, smooths edges by their luma in a single pass.
+    Fxaa,
+    /// `taa.comp`, blends each frame with the ones before it. Unlike the others it runs on
+    /// `hdr` right after the mesh pass, which jitters the camera and writes velocities for it.
+    Taa,
+}
+
+impl AntiAliasing {
+    /// Fullscreen pass applying it after the chain, sampling its source with a linear filter.
+    pub fn effect(self) -> Option<PostEffect> {
+        match self {
+            AntiAliasing::None | AntiAliasing::Taa => None,
+            AntiAliasing::Fxaa => Some(PostEffect::new("fxaa.frag")),
+        }
+    }
+}
+
+impl FromStr for AntiAliasing {
+    type Err = ParseAntiAliasingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(AntiAliasing::None),
+            "fxaa" => Ok(AntiAliasing::Fxaa),
+            "taa" => Ok(AntiAliasing::Taa),
+            _ => Err(ParseAntiAliasingError(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseAntiAliasingError(String);
+
+impl fmt::Display for ParseAntiAliasingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Unknown anti-aliasing {}, expected none, fxaa or taa.", self.0)
+    }
+}
+
+impl std::error::Error for ParseAntiAliasingError {}
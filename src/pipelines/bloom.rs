@@ -0,0 +1,113 @@
+use rendy::{
+    graph::{render::*, GraphBuilder, ImageId, NodeId},
+    hal,
+    resource::Filter,
+};
+
+use super::{
+    post_effect::{ParameterSource, ParameterValue, PipelineDesc, PostEffect},
+    PostEffectAux,
+};
+
+/// Settings of the bloom passes between the mesh pass and the post effect chain.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bloom {
+    /// Brightness above which pixels bloom, fading in over half of it below.
+    pub threshold: f32,
+    /// Scale of the blurred bright pixels added back onto the image.
+    pub intensity: f32,
+    /// How many times the image is halved, changing it needs a rebuild of the graph.
+    pub levels: usize,
+}
+
+impl Default for Bloom {
+    fn default() -> Self {
+        Self {
+            threshold: 1.,
+            intensity: 0.1,
+            levels: 5,
+        }
+    }
+}
+
+impl Bloom {
+    /// Value of a parameter of the bloom shaders.
+    pub fn parameter(&self, name: &str) -> Option<ParameterValue> {
+        match name {
+            "threshold" => Some(self.threshold.into()),
+            "intensity" => Some(self.intensity.into()),
+            _ => None,
+        }
+    }
+
+    /// Size of each level, starting at half of `size`.
+    pub fn level_sizes(&self, size: [u32; 2]) -> Vec<[u32; 2]> {
+        (1..=self.levels.max(1))
+            .map(|level| [(size[0] >> level).max(1), (size[1] >> level).max(1)])
+            .collect()
+    }
+}
+
+/// Adds the passes blooming `source` after `dependency`, returning the bloomed image
+/// and the pass writing it.
+///
+/// The bright parts of `source` are downsampled level by level, then upsampled back
+/// with each level added onto the one above. `source` itself is left alone.
+pub fn build_bloom<B: hal::Backend, T: PostEffectAux + ?Sized + 'static>(
+    graph_builder: &mut GraphBuilder<B, T>,
+    size: [u32; 2],
+    source: ImageId,
+    dependency: NodeId,
+    bloom: &Bloom,
+) -> (ImageId, NodeId) {
+    let add_pass = |graph_builder: &mut GraphBuilder<B, T>, desc: PipelineDesc, source, target, dependency| {
+        graph_builder.add_node(
+            desc.builder()
+                .with_image(source)
+                .into_subpass()
+                .with_dependency(dependency)
+                .with_color(target)
+                .into_pass()
+        )
+    };
+    let create_image = |graph_builder: &mut GraphBuilder<B, T>, size: [u32; 2]| {
+        graph_builder.create_image(
+            hal::image::Kind::D2(size[0], size[1], 1, 1),
+            1,
+            hal::format::Format::Rgba32Sfloat,
+            None,
+        )
+    };
+    let pass = |effect: PostEffect, blend| {
+        PipelineDesc::new(&effect, ParameterSource::Bloom)
+            .with_filter(Filter::Linear)
+            .with_blend(blend)
+    };
+    let downsample = PostEffect::new("bloom_downsample.frag");
+    let upsample = PostEffect::new("bloom_upsample.frag");
+    let add = Some(hal::pso::BlendState::ADD);
+
+    let levels: Vec<_> = bloom
+        .level_sizes(size)
+        .into_iter()
+        .map(|size| create_image(graph_builder, size))
+        .collect();
+
+    // every level is overwritten on the way down
+    let prefilter = pass(downsample.clone().with_define("PREFILTER", "1"), None);
+    let mut last = add_pass(graph_builder, prefilter, source, levels[0], dependency);
+    for pair in levels.windows(2) {
+        last = add_pass(graph_builder, pass(downsample.clone(), None), pair[0], pair[1], last);
+    }
+    for pair in levels.windows(2).rev() {
+        last = add_pass(graph_builder, pass(upsample.clone(), add), pair[1], pair[0], last);
+    }
+
+    let bloomed = create_image(graph_builder, size);
+    let copy = pass(PostEffect::new("passthrough.frag"), None);
+    last = add_pass(graph_builder, copy, source, bloomed, last);
+    let composite = pass(upsample.with_define("COMPOSITE", "1"), add);
+    last = add_pass(graph_builder, composite, levels[0], bloomed, last);
+
+    (bloomed, last)
+}
@@ -0,0 +1,349 @@
+use rendy::{
+    command::{
+        CommandPool, Compute, Family, IndividualReset, MultiShot, NoSimultaneousUse, Submit,
+    },
+    factory::Factory,
+    frame::{cirque::CommandCirque, Frames},
+    graph::{
+        gfx_acquire_barriers, gfx_release_barriers, BufferAccess, GraphContext, ImageAccess, Node,
+        NodeBuffer, NodeBuildError, NodeDesc, NodeImage, NodeSubmittable,
+    },
+    hal::{self, device::Device},
+    resource::{
+        DescriptorSet, DescriptorSetLayout, Escape, Filter, Handle, ImageView, ImageViewInfo,
+        Sampler, SamplerDesc, ViewKind, WrapMode,
+    },
+    shader::{Shader, ShaderSetBuilder, SpirvShader},
+};
+
+use super::PostEffectAux;
+use crate::shaders;
+
+/// Size of the `Luminance` block of `luminance.glsl`, 256 bins followed by the average.
+pub const LUMINANCE_SIZE: u64 = 256 * 4 + 4;
+
+/// Measures the average luminance of its image into the luminance buffer.
+///
+/// The average adapts to the current frame over `PostEffectAux::delta_time`,
+/// it is only kept as long as the graph is.
+#[derive(Debug)]
+pub struct LuminanceDesc {
+    histogram: SpirvShader,
+    average: SpirvShader,
+}
+
+impl LuminanceDesc {
+    /// `defines` are those of the pass reading the buffer, so both agree on the histogram.
+    pub fn new(defines: &[(String, String)]) -> Self {
+        let defines: Vec<_> = defines
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect();
+
+        Self {
+            histogram: shaders::get("histogram.comp", &defines),
+            average: shaders::get("average_luminance.comp", &defines),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Luminance<B: hal::Backend> {
+    command_pool: CommandPool<B, Compute, IndividualReset>,
+    command_cirque: CommandCirque<B, Compute>,
+    layout: B::PipelineLayout,
+    histogram: B::ComputePipeline,
+    average: B::ComputePipeline,
+    set: Escape<DescriptorSet<B>>,
+    image_sampler: Escape<Sampler<B>>,
+    image_view: Escape<ImageView<B>>,
+    buffers: Vec<NodeBuffer>,
+    images: Vec<NodeImage>,
+    /// Workgroups covering the image, one per 16x16 pixels.
+    groups: [u32; 2],
+    /// The buffer starts out with garbage, the first frame clears it.
+    cleared: bool,
+}
+
+impl<'a, B> NodeSubmittable<'a, B> for Luminance<B>
+where
+    B: hal::Backend,
+{
+    type Submittable = Submit<B, NoSimultaneousUse>;
+    type Submittables = Option<Submit<B, NoSimultaneousUse>>;
+}
+
+impl<B, T> Node<B, T> for Luminance<B>
+where
+    B: hal::Backend,
+    T: PostEffectAux + ?Sized,
+{
+    type Capability = Compute;
+
+    fn run<'a>(
+        &'a mut self,
+        ctx: &GraphContext<B>,
+        _factory: &Factory<B>,
+        aux: &T,
+        frames: &'a Frames<B>,
+    ) -> Option<Submit<B, NoSimultaneousUse>> {
+        let buffer = ctx
+            .get_buffer(self.buffers[0].id)
+            .expect("No luminance buffer supplied.");
+        let delta_time = if self.cleared { aux.delta_time() } else { 0. };
+        let clear = !self.cleared;
+        self.cleared = true;
+
+        let Self {
+            command_pool,
+            command_cirque,
+            layout,
+            histogram,
+            average,
+            set,
+            buffers,
+            images,
+            groups,
+            ..
+        } = self;
+
+        // recorded every frame, the time since the last one changes
+        let submit = command_cirque.encode(frames, command_pool, |cbuf| {
+            cbuf.or_reset(|cbuf| cbuf.reset()).init(|cbuf| {
+                let mut cbuf = cbuf.begin(MultiShot(NoSimultaneousUse), ());
+
+                unsafe {
+                    {
+                        let (stages, barriers) = gfx_acquire_barriers(ctx, &*buffers, &*images);
+                        cbuf.encoder()
+                            .pipeline_barrier(stages, hal::memory::Dependencies::empty(), barriers);
+                    }
+
+                    if clear {
+                        hal::command::CommandBuffer::fill_buffer(cbuf.raw(), buffer.raw(), .., 0);
+                        cbuf.encoder().pipeline_barrier(
+                            hal::pso::PipelineStage::TRANSFER..hal::pso::PipelineStage::COMPUTE_SHADER,
+                            hal::memory::Dependencies::empty(),
+                            Some(hal::memory::Barrier::Buffer {
+                                states: hal::buffer::Access::TRANSFER_WRITE
+                                    ..hal::buffer::Access::SHADER_READ | hal::buffer::Access::SHADER_WRITE,
+                                families: None,
+                                target: buffer.raw(),
+                                range: None..None,
+                            }),
+                        );
+                    }
+
+                    {
+                        let mut encoder = cbuf.encoder();
+                        encoder.bind_compute_descriptor_sets(layout, 0, Some(set.raw()), std::iter::empty());
+                        encoder.bind_compute_pipeline(histogram);
+                        encoder.dispatch(groups[0], groups[1], 1);
+
+                        // the average reads the histogram all workgroups added to
+                        encoder.pipeline_barrier(
+                            hal::pso::PipelineStage::COMPUTE_SHADER..hal::pso::PipelineStage::COMPUTE_SHADER,
+                            hal::memory::Dependencies::empty(),
+                            Some(hal::memory::Barrier::Buffer {
+                                states: hal::buffer::Access::SHADER_READ | hal::buffer::Access::SHADER_WRITE
+                                    ..hal::buffer::Access::SHADER_READ | hal::buffer::Access::SHADER_WRITE,
+                                families: None,
+                                target: buffer.raw(),
+                                range: None..None,
+                            }),
+                        );
+                        encoder.bind_compute_pipeline(average);
+                    }
+
+                    // the encoder only pushes constants for graphics pipelines
+
+                    hal::command::CommandBuffer::push_compute_constants(
+                        cbuf.raw(),
+                        layout,
+                        0,
+                        &[delta_time.to_bits()],
+                    );
+
+                    let mut encoder = cbuf.encoder();
+                    encoder.dispatch(1, 1, 1);
+                    let (stages, barriers) = gfx_release_barriers(ctx, &*buffers, &*images);
+                    encoder.pipeline_barrier(stages, hal::memory::Dependencies::empty(), barriers);
+                }
+
+                cbuf.finish()
+            })
+        });
+
+        Some(submit)
+    }
+
+    unsafe fn dispose(mut self, factory: &mut Factory<B>, _aux: &T) {
+        let pool = &mut self.command_pool;
+        self.command_cirque.dispose(|buffer| {
+            buffer.either_with(
+                &mut *pool,
+                |pool, executable| pool.free_buffers(Some(executable)),
+                |pool, pending| pool.free_buffers(Some(pending.mark_complete())),
+            );
+        });
+        factory.destroy_command_pool(self.command_pool);
+
+        factory.device().destroy_compute_pipeline(self.histogram);
+        factory.device().destroy_compute_pipeline(self.average);
+        factory.device().destroy_pipeline_layout(self.layout);
+        drop(self.set);
+        drop(self.image_view);
+        drop(self.image_sampler);
+    }
+}
+
+impl<B, T> NodeDesc<B, T> for LuminanceDesc
+where
+    B: hal::Backend,
+    T: PostEffectAux + ?Sized,
+{
+    type Node = Luminance<B>;
+
+    fn buffers(&self) -> Vec<BufferAccess> {
+        vec![BufferAccess {
+            access: hal::buffer::Access::SHADER_READ
+                | hal::buffer::Access::SHADER_WRITE
+                | hal::buffer::Access::TRANSFER_WRITE,
+            usage: hal::buffer::Usage::STORAGE | hal::buffer::Usage::TRANSFER_DST,
+            stages: hal::pso::PipelineStage::COMPUTE_SHADER | hal::pso::PipelineStage::TRANSFER,
+        }]
+    }
+
+    fn images(&self) -> Vec<ImageAccess> {
+        vec![ImageAccess {
+            access: hal::image::Access::SHADER_READ,
+            usage: hal::image::Usage::SAMPLED,
+            layout: hal::image::Layout::ShaderReadOnlyOptimal,
+            stages: hal::pso::PipelineStage::COMPUTE_SHADER,
+        }]
+    }
+
+    fn build<'a>(
+        self,
+        ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        family: &mut Family<B>,
+        _queue: usize,
+        _aux: &T,
+        buffers: Vec<NodeBuffer>,
+        images: Vec<NodeImage>,
+    ) -> Result<Self::Node, NodeBuildError> {
+        assert_eq!(buffers.len(), 1);
+        assert_eq!(images.len(), 1);
+
+        // both shaders declare the same bindings, so one layout fits them
+        let reflection = ShaderSetBuilder::default()
+            .with_compute(&self.histogram).unwrap()
+            .reflect()
+            .unwrap();
+        let set_layout: Handle<DescriptorSetLayout<B>> = factory
+            .create_descriptor_set_layout(reflection.layout().unwrap().sets.remove(0).bindings)
+            .map_err(NodeBuildError::OutOfMemory)?
+            .into();
+        let push_constants = ShaderSetBuilder::default()
+            .with_compute(&self.average).unwrap()
+            .reflect()
+            .unwrap()
+            .push_constants;
+
+        let layout = unsafe {
+            factory
+                .device()
+                .create_pipeline_layout(Some(set_layout.raw()), push_constants)
+                .map_err(NodeBuildError::OutOfMemory)?
+        };
+
+        let create_pipeline = |shader: &SpirvShader| unsafe {
+            let module = shader.module(factory).unwrap();
+            let pipeline = factory.device().create_compute_pipeline(
+                &hal::pso::ComputePipelineDesc::new(
+                    hal::pso::EntryPoint {
+                        entry: shader.entry(),
+                        module: &module,
+                        specialization: hal::pso::Specialization::default(),
+                    },
+                    &layout,
+                ),
+                None,
+            );
+            factory.device().destroy_shader_module(module);
+            pipeline.map_err(NodeBuildError::Pipeline)
+        };
+        let histogram = create_pipeline(&self.histogram)?;
+        let average = create_pipeline(&self.average)?;
+
+        let image = ctx
+            .get_image(images[0].id)
+            .expect("No input image supplied.");
+        let extent = image.kind().extent();
+
+        let image_sampler = factory
+            .create_sampler(SamplerDesc::new(Filter::Nearest, WrapMode::Clamp))
+            .unwrap();
+        let image_view = factory
+            .create_image_view(
+                image.clone(),
+                ImageViewInfo {
+                    view_kind: ViewKind::D2,
+                    format: image.format(),
+                    swizzle: hal::format::Swizzle::NO,
+                    range: images[0].range.clone(),
+                },
+            )
+            .expect("Could not create image view");
+
+        let buffer = ctx
+            .get_buffer(buffers[0].id)
+            .expect("No luminance buffer supplied.");
+
+        let set = factory
+            .create_descriptor_set(set_layout)
+            .map_err(NodeBuildError::OutOfMemory)?;
+        unsafe {
+            factory.write_descriptor_sets(vec![
+                hal::pso::DescriptorSetWrite {
+                    set: set.raw(),
+                    binding: 0,
+                    array_offset: 0,
+                    descriptors: Some(hal::pso::Descriptor::CombinedImageSampler(
+                        image_view.raw(),
+                        hal::image::Layout::ShaderReadOnlyOptimal,
+                        image_sampler.raw(),
+                    )),
+                },
+                hal::pso::DescriptorSetWrite {
+                    set: set.raw(),
+                    binding: 1,
+                    array_offset: 0,
+                    descriptors: Some(hal::pso::Descriptor::Buffer(buffer.raw(), None..None)),
+                },
+            ]);
+        }
+
+        let command_pool = factory
+            .create_command_pool(family)
+            .map_err(NodeBuildError::OutOfMemory)?
+            .with_capability::<Compute>()
+            .expect("Graph builder must provide family with Compute capability");
+
+        Ok(Luminance {
+            command_pool,
+            command_cirque: CommandCirque::new(),
+            layout,
+            histogram,
+            average,
+            set,
+            image_sampler,
+            image_view,
+            buffers,
+            images,
+            groups: [extent.width.div_ceil(16), extent.height.div_ceil(16)],
+            cleared: false,
+        })
+    }
+}
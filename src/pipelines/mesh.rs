@@ -1,136 +1,448 @@
 use rendy::{
-    command::{Families, QueueId, RenderPassEncoder},
-    factory::{Config, Factory, ImageState},
-    graph::{
-        present::PresentNode, render::*, Graph, GraphBuilder, GraphContext, NodeBuffer, NodeImage,
-    },
-    hal::{self, adapter::PhysicalDevice, pso::ShaderStageFlags},
-    init::winit::{
-        self,
-        dpi::{PhysicalSize, Size},
-        event::{DeviceEvent, Event, WindowEvent},
-        event_loop::{ControlFlow, EventLoop},
-        window::WindowBuilder,
-    },
-    init::AnyWindowedRendy,
+    command::{QueueId, RenderPassEncoder},
+    factory::Factory,
+    graph::{render::*, GraphContext, ImageAccess, NodeBuffer, NodeImage},
+    hal::{self, adapter::PhysicalDevice, device::Device, pso::ShaderStageFlags},
     memory::Dynamic,
-    mesh::{AsVertex, Mesh, Position},
-    resource::{Buffer, BufferInfo, DescriptorSet, DescriptorSetLayout, Escape, Handle},
-    shader::{
-        ShaderKind, ShaderSet, ShaderSetBuilder, SourceLanguage, SourceShaderInfo, SpirvReflection,
-        SpirvShader,
+    mesh::VertexFormat,
+    resource::{
+        Buffer, BufferInfo, DescriptorSet, DescriptorSetLayout, Escape, Filter, Handle, ImageView,
+        ImageViewInfo, Sampler, SamplerDesc, ViewKind, WrapMode,
     },
-    texture::{image::ImageTextureConfig, Texture},
+    shader::{ShaderSetBuilder, SpirvReflection},
+    texture::Texture,
 };
 
-use std::{
-    fs::read_to_string,
-};
+use nalgebra::Matrix4;
 
-use super::*;
-
-lazy_static::lazy_static! {
-    static ref VERT_SRC: String = read_to_string(concat!(env!("CARGO_MANIFEST_DIR"),"/assets/mesh.vert")).expect("Couldn't open shader file.");
-    static ref FRAG_SRC: String = read_to_string(concat!(env!("CARGO_MANIFEST_DIR"),"/assets/mesh.frag")).expect("Couldn't open shader file.");
-
-    static ref VERTEX: SpirvShader = {
-        SourceShaderInfo::new(
-            VERT_SRC.as_str(),
-            "mesh.vert",
-            ShaderKind::Vertex,
-            SourceLanguage::GLSL,
-            "main",
-        ).precompile().unwrap()
-    };
-    static ref FRAGMENT: SpirvShader = {
-        SourceShaderInfo::new(
-            FRAG_SRC.as_str(),
-            "mesh.frag",
-            ShaderKind::Fragment,
-            SourceLanguage::GLSL,
-            "main",
-        ).precompile().unwrap()
-    };
-    static ref SHADERS: rendy::shader::ShaderSetBuilder = rendy::shader::ShaderSetBuilder::default()
-        .with_vertex(&*VERTEX).unwrap()
-        .with_fragment(&*FRAGMENT).unwrap();
-
-    static ref SHADER_REFLECTION: SpirvReflection = SHADERS.reflect().unwrap();
+use super::{antialiasing::AntiAliasing, shadow::atlas_columns, vertex_input, MeshAux};
+use crate::{shaders, texture::TextureData, RenderSettings};
+
+/// Shaders as they were when the graph was built, later changes need a rebuild.
+/// Its only image is the shadow atlas of `shadow::ShadowDesc`.
+///
+/// Not a `SimpleGraphicsPipelineDesc`, those can't render to multisampled images.
+#[derive(Debug)]
+pub struct PipelineDesc {
+    shaders: ShaderSetBuilder,
+    reflection: SpirvReflection,
+    samples: hal::image::NumSamples,
+    velocity: bool,
+    cascades: usize,
 }
 
-#[derive(Debug, Default)]
-pub struct PipelineDesc;
+impl Default for PipelineDesc {
+    fn default() -> Self {
+        Self::new(&RenderSettings::default())
+    }
+}
+
+/// `Camera` block of `mesh.vert`, the jittered, unjittered and previous transform.
+const CAMERA_SIZE: u64 = 3 * 64;
+
+/// `Light` block of `mesh.frag`, a transform for each cascade, the direction and the color.
+fn light_size(cascades: usize) -> u64 {
+    cascades as u64 * 64 + 2 * 16
+}
+
+impl PipelineDesc {
+    /// Renders with the samples and shadow cascades of `settings`. With temporal anti-aliasing
+    /// the pass writes where each pixel was the frame before to a second color image, see `taa::TaaDesc`.
+    pub fn new(settings: &RenderSettings) -> Self {
+        let velocity = settings.antialiasing == AntiAliasing::Taa;
+        let cascades = settings.cascades.to_string();
+        let columns = atlas_columns(settings.cascades).to_string();
+        let mut defines = vec![("CASCADES", cascades.as_str()), ("CASCADE_COLUMNS", columns.as_str())];
+        if velocity {
+            defines.push(("VELOCITY", "1"));
+        }
+        if settings.debug_cascades {
+            defines.push(("DEBUG_CASCADES", "1"));
+        }
+
+        let shaders = ShaderSetBuilder::default()
+            .with_vertex(&shaders::get("mesh.vert", &defines)).unwrap()
+            .with_fragment(&shaders::get("mesh.frag", &defines)).unwrap();
+        let reflection = shaders.reflect().unwrap();
+        Self {
+            shaders,
+            reflection,
+            samples: settings.samples,
+            velocity,
+            cascades: settings.cascades,
+        }
+    }
+
+    /// Every attribute comes from its own vertex buffer, see `MeshData::build`.
+    /// Meshes expect the formats sorted, which doesn't follow the locations, see `vertex_input`.
+    fn vertex_formats(&self) -> Vec<VertexFormat> {
+        let mut formats: Vec<_> = ["position", "normal", "tex_coord"]
+            .iter()
+            .map(|&name| self.reflection.attributes(&[name]).unwrap())
+            .collect();
+        formats.sort();
+        formats
+    }
+}
 
 #[derive(Debug)]
-pub struct Pipeline;
+pub struct Pipeline<B: hal::Backend> {
+    set_layouts: Vec<Handle<DescriptorSetLayout<B>>>,
+    layout: B::PipelineLayout,
+    graphics_pipeline: B::GraphicsPipeline,
+    vertex_formats: Vec<VertexFormat>,
+    /// `Camera` and `Light` blocks of each frame in flight, `uniform_stride` bytes apart.
+    /// The `Light` block starts `light_offset` bytes into them.
+    uniforms: Escape<Buffer<B>>,
+    uniform_stride: u64,
+    light_offset: u64,
+    cascades: usize,
+    frame_sets: Vec<Escape<DescriptorSet<B>>>,
+    shadow_view: Escape<ImageView<B>>,
+    shadow_sampler: Escape<Sampler<B>>,
+    /// Unjittered transform of the last frame.
+    previous_transform: Option<Matrix4<f32>>,
+    /// Bound for objects without a texture.
+    white: Texture<B>,
+    white_set: Escape<DescriptorSet<B>>,
+    /// One set for every texture of the aux, in the same order.
+    sets: Vec<Escape<DescriptorSet<B>>>,
+}
+
+/// Set binding `texture` as the `albedo` of `mesh.frag`.
+fn texture_set<B: hal::Backend>(
+    factory: &Factory<B>,
+    layout: &Handle<DescriptorSetLayout<B>>,
+    texture: &Texture<B>,
+) -> Escape<DescriptorSet<B>> {
+    let set = factory.create_descriptor_set(layout.clone()).unwrap();
+    unsafe {
+        factory.write_descriptor_sets(Some(hal::pso::DescriptorSetWrite {
+            set: set.raw(),
+            binding: 0,
+            array_offset: 0,
+            descriptors: Some(hal::pso::Descriptor::CombinedImageSampler(
+                texture.view().raw(),
+                hal::image::Layout::ShaderReadOnlyOptimal,
+                texture.sampler().raw(),
+            )),
+        }));
+    }
+    set
+}
 
-impl<B> SimpleGraphicsPipelineDesc<B, Aux<B>> for PipelineDesc
+impl<B, T> RenderGroupDesc<B, T> for PipelineDesc
 where
     B: hal::Backend,
+    T: MeshAux<B> + ?Sized,
 {
-    type Pipeline = Pipeline;
-
-    fn load_shader_set(&self, factory: &mut Factory<B>, _aux: &Aux<B>) -> ShaderSet<B> {
-        SHADERS.build(factory, Default::default()).unwrap()
+    fn colors(&self) -> usize {
+        if self.velocity { 2 } else { 1 }
     }
-    
-    fn vertices(
-        &self,
-    ) -> Vec<(
-        Vec<hal::pso::Element<hal::format::Format>>,
-        hal::pso::ElemStride,
-        hal::pso::VertexInputRate,
-    )> {
-        vec![SHADER_REFLECTION
-        .attributes(&["position"])
-        .unwrap()
-        .gfx_vertex_input_desc(hal::pso::VertexInputRate::Vertex)]
-    }
-    
-    fn layout(&self) -> Layout {
-        SHADER_REFLECTION.layout().unwrap()
+
+    fn images(&self) -> Vec<ImageAccess> {
+        vec![ImageAccess {
+            access: hal::image::Access::SHADER_READ,
+            usage: hal::image::Usage::SAMPLED,
+            layout: hal::image::Layout::ShaderReadOnlyOptimal,
+            stages: hal::pso::PipelineStage::FRAGMENT_SHADER,
+        }]
     }
-    
-    fn build(
+
+    fn build<'a>(
         self,
-        _ctx: &GraphContext<B>,
-        _factory: &mut Factory<B>,
-        _queue: QueueId,
-        _aux: &Aux<B>,
+        ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        queue: QueueId,
+        aux: &T,
+        framebuffer_width: u32,
+        framebuffer_height: u32,
+        subpass: hal::pass::Subpass<'_, B>,
         _buffers: Vec<NodeBuffer>,
-        _images: Vec<NodeImage>,
-        _set_layouts: &[Handle<DescriptorSetLayout<B>>],
-    ) -> Result<Self::Pipeline, hal::pso::CreationError> {        
-        Ok(Pipeline{})
+        images: Vec<NodeImage>,
+    ) -> Result<Box<dyn RenderGroup<B, T>>, hal::pso::CreationError> {
+        assert_eq!(images.len(), 1);
+
+        let layout = self.reflection.layout().unwrap();
+        let set_layouts = layout
+            .sets
+            .into_iter()
+            .map(|set| factory.create_descriptor_set_layout(set.bindings).map(Handle::from))
+            .collect::<Result<Vec<_>, _>>()?;
+        let pipeline_layout = unsafe {
+            factory
+                .device()
+                .create_pipeline_layout(set_layouts.iter().map(|layout| layout.raw()), layout.push_constants)
+                .map_err(hal::pso::CreationError::OutOfMemory)?
+        };
+
+        let vertex_formats = self.vertex_formats();
+        let (vertex_buffers, attributes) = vertex_input(&self.reflection, &vertex_formats);
+
+        let rect = hal::pso::Rect {
+            x: 0,
+            y: 0,
+            w: framebuffer_width as i16,
+            h: framebuffer_height as i16,
+        };
+        let multisampling = if self.samples > 1 {
+            Some(hal::pso::Multisampling {
+                rasterization_samples: self.samples,
+                sample_shading: None,
+                sample_mask: !0,
+                alpha_coverage: false,
+                alpha_to_one: false,
+            })
+        } else {
+            None
+        };
+
+        let mut targets = vec![hal::pso::ColorBlendDesc {
+            mask: hal::pso::ColorMask::ALL,
+            blend: Some(hal::pso::BlendState::ALPHA),
+        }];
+        if self.velocity {
+            // velocities are written as they are
+            targets.push(hal::pso::ColorBlendDesc {
+                mask: hal::pso::ColorMask::ALL,
+                blend: None,
+            });
+        }
+
+        let mut shader_set = self.shaders.build(factory, Default::default()).unwrap();
+        let graphics_pipeline = unsafe {
+            factory.device().create_graphics_pipeline(
+                &hal::pso::GraphicsPipelineDesc {
+                    shaders: shader_set.raw().unwrap(),
+                    rasterizer: hal::pso::Rasterizer::FILL,
+                    vertex_buffers,
+                    attributes,
+                    input_assembler: hal::pso::InputAssemblerDesc {
+                        primitive: hal::pso::Primitive::TriangleList,
+                        with_adjacency: false,
+                        restart_index: None,
+                    },
+                    blender: hal::pso::BlendDesc {
+                        logic_op: None,
+                        targets,
+                    },
+                    depth_stencil: hal::pso::DepthStencilDesc {
+                        depth: Some(hal::pso::DepthTest {
+                            fun: hal::pso::Comparison::Less,
+                            write: true,
+                        }),
+                        depth_bounds: false,
+                        stencil: None,
+                    },
+                    multisampling,
+                    baked_states: hal::pso::BakedStates {
+                        viewport: Some(hal::pso::Viewport {
+                            rect,
+                            depth: 0.0..1.0,
+                        }),
+                        scissor: Some(rect),
+                        blend_color: None,
+                        depth_bounds: None,
+                    },
+                    layout: &pipeline_layout,
+                    subpass,
+                    flags: hal::pso::PipelineCreationFlags::empty(),
+                    parent: hal::pso::BasePipeline::None,
+                },
+                None,
+            )
+        };
+        shader_set.dispose(factory);
+        let graphics_pipeline = graphics_pipeline?;
+
+        let white = TextureData::white().build(factory, queue);
+        let white_set = texture_set(factory, &set_layouts[0], &white);
+        let sets = aux
+            .textures()
+            .iter()
+            .map(|texture| texture_set(factory, &set_layouts[0], texture))
+            .collect();
+
+        let shadow_atlas = ctx.get_image(images[0].id).expect("No shadow atlas supplied.");
+        let shadow_view = factory
+            .create_image_view(
+                shadow_atlas.clone(),
+                ImageViewInfo {
+                    view_kind: ViewKind::D2,
+                    format: shadow_atlas.format(),
+                    swizzle: hal::format::Swizzle::NO,
+                    range: hal::image::SubresourceRange {
+                        aspects: hal::format::Aspects::DEPTH,
+                        levels: 0..1,
+                        layers: 0..1,
+                    },
+                },
+            )
+            .expect("Could not create image view");
+        // linear filtering blends the comparisons
+        let shadow_sampler = factory
+            .create_sampler(SamplerDesc {
+                comparison: Some(hal::pso::Comparison::LessEqual),
+                border: hal::image::PackedColor(!0),
+                ..SamplerDesc::new(Filter::Linear, WrapMode::Border)
+            })
+            .unwrap();
+
+        let alignment = factory.physical().limits().min_uniform_buffer_offset_alignment;
+        let light_size = light_size(self.cascades);
+        let light_offset = CAMERA_SIZE.div_ceil(alignment) * alignment;
+        let uniform_stride = light_offset + light_size.div_ceil(alignment) * alignment;
+        let uniforms = factory
+            .create_buffer(
+                BufferInfo {
+                    size: uniform_stride * ctx.frames_in_flight as u64,
+                    usage: hal::buffer::Usage::UNIFORM,
+                },
+                Dynamic,
+            )
+            .unwrap();
+        let frame_sets = (0..ctx.frames_in_flight as u64)
+            .map(|frame| {
+                let set = factory.create_descriptor_set(set_layouts[1].clone()).unwrap();
+                let camera = frame * uniform_stride;
+                let light = camera + light_offset;
+                let write = |binding, descriptor| hal::pso::DescriptorSetWrite {
+                    set: set.raw(),
+                    binding,
+                    array_offset: 0,
+                    descriptors: Some(descriptor),
+                };
+                unsafe {
+                    factory.write_descriptor_sets(vec![
+                        write(0, hal::pso::Descriptor::Buffer(uniforms.raw(), Some(camera)..Some(camera + CAMERA_SIZE))),
+                        write(1, hal::pso::Descriptor::Buffer(uniforms.raw(), Some(light)..Some(light + light_size))),
+                        write(
+                            2,
+                            hal::pso::Descriptor::CombinedImageSampler(
+                                shadow_view.raw(),
+                                hal::image::Layout::ShaderReadOnlyOptimal,
+                                shadow_sampler.raw(),
+                            ),
+                        ),
+                    ]);
+                }
+                set
+            })
+            .collect();
+
+        Ok(Box::new(Pipeline {
+            set_layouts,
+            layout: pipeline_layout,
+            graphics_pipeline,
+            vertex_formats,
+            uniforms,
+            uniform_stride,
+            light_offset,
+            cascades: self.cascades,
+            frame_sets,
+            shadow_view,
+            shadow_sampler,
+            previous_transform: None,
+            white,
+            white_set,
+            sets,
+        }))
     }
 }
 
-impl<B> SimpleGraphicsPipeline<B, Aux<B>> for Pipeline
+impl<B, T> RenderGroup<B, T> for Pipeline<B>
 where
     B: hal::Backend,
+    T: MeshAux<B> + ?Sized,
 {
-    type Desc = PipelineDesc;
+    fn prepare(
+        &mut self,
+        factory: &Factory<B>,
+        _queue: QueueId,
+        index: usize,
+        _subpass: hal::pass::Subpass<'_, B>,
+        aux: &T,
+    ) -> PrepareResult {
+        // textures are usually uploaded after the graph is built
+        for texture in &aux.textures()[self.sets.len()..] {
+            self.sets.push(texture_set(factory, &self.set_layouts[0], texture));
+        }
+
+        // the first frame has nothing to move from
+        let transform = aux.camera().get_unjittered_transform();
+        let previous = self.previous_transform.replace(transform).unwrap_or(transform);
+        let light = aux.light();
+        let mut light_block: Vec<f32> = light
+            .get_cascade_transforms(aux.camera(), self.cascades)
+            .iter()
+            .flat_map(|transform| transform.as_slice().to_vec())
+            .collect();
+        light_block.extend(light.direction.normalize().iter().chain(&[0.]));
+        light_block.extend(light.color.iter().chain(&[0.]));
+        let offset = self.uniform_stride * index as u64;
+        unsafe {
+            factory
+                .upload_visible_buffer(
+                    &mut self.uniforms,
+                    offset,
+                    &[aux.camera().get_transform(), transform, previous],
+                )
+                .unwrap();
+            factory
+                .upload_visible_buffer(&mut self.uniforms, offset + self.light_offset, &light_block)
+                .unwrap();
+        }
+        PrepareResult::DrawRecord
+    }
 
-    fn draw(
+    fn draw_inline(
         &mut self,
-        layout: &<B as hal::Backend>::PipelineLayout,
-        mut encoder: RenderPassEncoder<B>,
-        _index: usize,
-        aux: &Aux<B>,
+        mut encoder: RenderPassEncoder<'_, B>,
+        index: usize,
+        _subpass: hal::pass::Subpass<'_, B>,
+        aux: &T,
     ) {
-        if let Some(ref mesh) = aux.mesh {
+        let layout = &self.layout;
+        encoder.bind_graphics_pipeline(&self.graphics_pipeline);
+        unsafe {
+            encoder.bind_graphics_descriptor_sets(
+                layout,
+                1,
+                Some(self.frame_sets[index].raw()),
+                std::iter::empty(),
+            );
+        }
+
+        for instance in aux.scene().instances() {
+            let set = match instance.texture {
+                Some(texture) => &self.sets[texture],
+                None => &self.white_set,
+            };
             unsafe {
-                let data = std::slice::from_raw_parts(
-                    aux.camera.get_transform().as_ptr() as *const u32,
-                    16,
-                );
-                encoder.push_constants(layout, ShaderStageFlags::VERTEX, 0, data);
+                encoder.bind_graphics_descriptor_sets(layout, 0, Some(set.raw()), std::iter::empty());
+                // both stages see the whole `Instance` block
+                let stages = ShaderStageFlags::VERTEX | ShaderStageFlags::FRAGMENT;
+                let model = std::slice::from_raw_parts(instance.model.as_ptr() as *const u32, 16);
+                encoder.push_constants(layout, stages, 0, model);
+                let color = std::slice::from_raw_parts(instance.color.as_ptr() as *const u32, 4);
+                encoder.push_constants(layout, stages, 64, color);
             }
-            let vertex = [SHADER_REFLECTION.attributes(&["position"]).unwrap()];
-            mesh.bind_and_draw(0, &vertex, 0..1, &mut encoder).unwrap();
+            aux.meshes()[instance.mesh].bind_and_draw(0, &self.vertex_formats, 0..1, &mut encoder).unwrap();
         }
     }
 
-    fn dispose(self, _factory: &mut Factory<B>, _aux: &Aux<B>) {}
+    fn dispose(self: Box<Self>, factory: &mut Factory<B>, _aux: &T) {
+        unsafe {
+            factory.device().destroy_graphics_pipeline(self.graphics_pipeline);
+            factory.device().destroy_pipeline_layout(self.layout);
+        }
+        drop(self.frame_sets);
+        drop(self.shadow_sampler);
+        drop(self.shadow_view);
+        drop(self.uniforms);
+        drop(self.sets);
+        drop(self.white_set);
+        drop(self.white);
+        drop(self.set_layouts);
+    }
 }
-
@@ -1,4 +1,95 @@
-use super::Aux;
+use rendy::{
+    hal,
+    mesh::{Mesh, VertexFormat},
+    shader::SpirvReflection,
+    texture::Texture,
+};
 
+use crate::{Camera, DirectionalLight, RenderSettings, Scene};
+
+pub mod antialiasing;
+pub mod bloom;
+pub mod luminance;
 pub mod mesh;
-pub mod post_effect;
\ No newline at end of file
+pub mod post_effect;
+pub mod resolve;
+pub mod shadow;
+pub mod taa;
+
+/// Vertex buffers and attributes of a pipeline drawing meshes uploaded by `MeshData::build`,
+/// which puts every attribute in its own buffer.
+///
+/// `formats` are sorted the way `Mesh::bind` binds them, so the binding of each attribute is
+/// the index of its format, while its location is the one the vertex shader declares.
+pub fn vertex_input(
+    reflection: &SpirvReflection,
+    formats: &[VertexFormat],
+) -> (Vec<hal::pso::VertexBufferDesc>, Vec<hal::pso::AttributeDesc>) {
+    let mut vertex_buffers = Vec::new();
+    let mut attributes = Vec::new();
+    for (binding, format) in formats.iter().enumerate() {
+        vertex_buffers.push(hal::pso::VertexBufferDesc {
+            binding: binding as u32,
+            stride: format.stride,
+            rate: hal::pso::VertexInputRate::Vertex,
+        });
+        for attribute in &format.attributes {
+            let key = (attribute.name().to_string(), attribute.index());
+            attributes.push(hal::pso::AttributeDesc {
+                location: reflection.input_attributes[&key].location,
+                binding: binding as u32,
+                element: *attribute.element(),
+            });
+        }
+    }
+    (vertex_buffers, attributes)
+}
+
+/// Data the mesh pipeline needs from the auxiliary type of the graph.
+pub trait MeshAux<B: hal::Backend> {
+    /// Meshes the objects of the scene refer to.
+    fn meshes(&self) -> &[Mesh<B>];
+    /// Albedo textures the objects of the scene refer to, only ever appended to.
+    fn textures(&self) -> &[Texture<B>];
+    /// Objects drawn every frame.
+    fn scene(&self) -> &Scene;
+    /// Camera the meshes are viewed through.
+    fn camera(&self) -> &Camera;
+    /// Light the meshes are shaded by, its shadow cascades cover what the camera sees.
+    fn light(&self) -> &DirectionalLight;
+}
+
+impl<B: hal::Backend> MeshAux<B> for crate::Aux<B> {
+    fn meshes(&self) -> &[Mesh<B>] {
+        &self.meshes
+    }
+    fn textures(&self) -> &[Texture<B>] {
+        &self.textures
+    }
+    fn scene(&self) -> &Scene {
+        &self.scene
+    }
+    fn camera(&self) -> &Camera {
+        &self.camera
+    }
+    fn light(&self) -> &DirectionalLight {
+        &self.light
+    }
+}
+
+/// Data the post effect pipeline needs from the auxiliary type of the graph.
+pub trait PostEffectAux {
+    /// Settings the graph was built with, parameters are read every frame.
+    fn settings(&self) -> &RenderSettings;
+    /// Seconds since the last frame, the average luminance adapts over it.
+    fn delta_time(&self) -> f32;
+}
+
+impl<B: hal::Backend> PostEffectAux for crate::Aux<B> {
+    fn settings(&self) -> &RenderSettings {
+        &self.settings
+    }
+    fn delta_time(&self) -> f32 {
+        self.delta_time
+    }
+}
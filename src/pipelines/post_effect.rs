@@ -1,66 +1,329 @@
 use rendy::{
     command::{QueueId, RenderPassEncoder},
     factory::Factory,
-    graph::{render::*, GraphContext, ImageAccess, NodeBuffer, NodeImage},
-    hal::{self, device::Device, pso::DescriptorPool, pso::ShaderStageFlags},
+    graph::{render::*, BufferAccess, GraphContext, ImageAccess, NodeBuffer, NodeImage},
+    hal::{self, device::Device, pso::DescriptorPool},
     resource::{
-        Buffer, BufferInfo, DescriptorSetLayout, Escape, Filter, Handle, ImageView, ImageViewInfo,
-        Sampler, SamplerDesc, ViewKind, WrapMode,
+        DescriptorSetLayout, Escape, Filter, Handle, ImageView, ImageViewInfo, Sampler,
+        SamplerDesc, ViewKind, WrapMode,
     },
-    shader::{PathBufShaderInfo, ShaderKind, ShaderSet, SourceLanguage, SpirvShader, SourceShaderInfo, SpirvReflection},
+    shader::{Shader, ShaderSet, ShaderSetBuilder, SpirvReflection, SpirvShader},
 };
+use spirv_reflect::types::ReflectTypeFlags;
 
-use std::{
-    fs::read_to_string,
-};
+use super::PostEffectAux;
+use crate::shaders;
 
-use super::*;
+/// Fullscreen pass of the post effect chain.
+///
+/// The fragment shader samples the previous image of the chain as `source`
+/// from binding `0` of set `0`. Members of its push constant block are the
+/// parameters of the effect, set through `parameters`. Shaders that declare the
+/// `Luminance` block of `luminance.glsl` at binding `1` get the average luminance of `source`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PostEffect {
+    /// Fragment shader, relative to the assets directory.
+    pub shader: String,
+    /// Defines the shader is compiled with.
+    pub defines: Vec<(String, String)>,
+    /// Values of the push constant members, read every frame. Members without a value are zero.
+    pub parameters: Vec<(String, ParameterValue)>,
+}
 
-lazy_static::lazy_static! {
-    static ref VERT_SRC: String = read_to_string(concat!(env!("CARGO_MANIFEST_DIR"),"/assets/fullscreen_triangle.vert")).expect("Couldn't open shader file.");
-    static ref FRAG_SRC: String = read_to_string(concat!(env!("CARGO_MANIFEST_DIR"),"/assets/posteffect.frag")).expect("Couldn't open shader file.");
+impl PostEffect {
+    pub fn new(shader: &str) -> Self {
+        Self {
+            shader: shader.to_string(),
+            defines: Vec::new(),
+            parameters: Vec::new(),
+        }
+    }
 
-    static ref VERTEX: SpirvShader = {
-        SourceShaderInfo::new(
-            VERT_SRC.as_str(),
-            "fullscreen_triangle.vert",
-            ShaderKind::Vertex,
-            SourceLanguage::GLSL,
-            "main",
-        ).precompile().unwrap()
-    };
-    static ref FRAGMENT: SpirvShader = {
-        SourceShaderInfo::new(
-            FRAG_SRC.as_str(),
-            "posteffect.frag",
-            ShaderKind::Fragment,
-            SourceLanguage::GLSL,
-            "main",
-        ).precompile().unwrap()
+    pub fn with_define(mut self, name: &str, value: &str) -> Self {
+        self.defines.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn with_parameter(mut self, name: &str, value: impl Into<ParameterValue>) -> Self {
+        self.set_parameter(name, value);
+        self
+    }
+
+    pub fn set_parameter(&mut self, name: &str, value: impl Into<ParameterValue>) {
+        let value = value.into();
+        match self.parameters.iter_mut().find(|(n, _)| n == name) {
+            Some((_, v)) => *v = value,
+            None => self.parameters.push((name.to_string(), value)),
+        }
+    }
+
+    pub fn parameter(&self, name: &str) -> Option<ParameterValue> {
+        self.parameters.iter().find(|(n, _)| n == name).map(|&(_, v)| v)
+    }
+}
+
+/// GLSL types a parameter can have.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParameterType {
+    Float,
+    Vec2,
+    Vec3,
+    Vec4,
+}
+
+impl ParameterType {
+    pub fn components(self) -> usize {
+        match self {
+            ParameterType::Float => 1,
+            ParameterType::Vec2 => 2,
+            ParameterType::Vec3 => 3,
+            ParameterType::Vec4 => 4,
+        }
+    }
+}
+
+/// Value of a parameter, colors are `Vec3` or `Vec4` in linear space.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ParameterValue {
+    Float(f32),
+    Vec2([f32; 2]),
+    Vec3([f32; 3]),
+    Vec4([f32; 4]),
+}
+
+impl ParameterValue {
+    pub fn ty(&self) -> ParameterType {
+        match self {
+            ParameterValue::Float(_) => ParameterType::Float,
+            ParameterValue::Vec2(_) => ParameterType::Vec2,
+            ParameterValue::Vec3(_) => ParameterType::Vec3,
+            ParameterValue::Vec4(_) => ParameterType::Vec4,
+        }
+    }
+
+    pub fn components(&self) -> &[f32] {
+        match self {
+            ParameterValue::Float(v) => std::slice::from_ref(v),
+            ParameterValue::Vec2(v) => v,
+            ParameterValue::Vec3(v) => v,
+            ParameterValue::Vec4(v) => v,
+        }
+    }
+
+    /// Float or vector with as many components, `None` for more than four.
+    pub fn from_components(components: &[f32]) -> Option<Self> {
+        Some(match *components {
+            [x] => ParameterValue::Float(x),
+            [x, y] => ParameterValue::Vec2([x, y]),
+            [x, y, z] => ParameterValue::Vec3([x, y, z]),
+            [x, y, z, w] => ParameterValue::Vec4([x, y, z, w]),
+            _ => return None,
+        })
+    }
+}
+
+impl From<f32> for ParameterValue {
+    fn from(value: f32) -> Self {
+        ParameterValue::Float(value)
+    }
+}
+
+impl From<[f32; 2]> for ParameterValue {
+    fn from(value: [f32; 2]) -> Self {
+        ParameterValue::Vec2(value)
+    }
+}
+
+impl From<[f32; 3]> for ParameterValue {
+    fn from(value: [f32; 3]) -> Self {
+        ParameterValue::Vec3(value)
+    }
+}
+
+impl From<[f32; 4]> for ParameterValue {
+    fn from(value: [f32; 4]) -> Self {
+        ParameterValue::Vec4(value)
+    }
+}
+
+/// Member of the push constant block of a fragment shader.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Parameter {
+    pub name: String,
+    /// Offset in bytes from the start of the block.
+    pub offset: u32,
+    pub ty: ParameterType,
+}
+
+/// Parameters of `shader` and the size of their block in bytes.
+///
+/// Panics if the block has members of any other type than the ones in `ParameterType`.
+pub fn reflect_parameters(name: &str, shader: &SpirvShader) -> (Vec<Parameter>, u32) {
+    let module = spirv_reflect::ShaderModule::load_u32_data(&shader.spirv().unwrap()).unwrap();
+    let blocks = module.enumerate_push_constant_blocks(None).unwrap();
+    let block = match blocks.first() {
+        Some(block) => block,
+        None => return (Vec::new(), 0),
     };
-    static ref SHADERS: rendy::shader::ShaderSetBuilder = rendy::shader::ShaderSetBuilder::default()
-        .with_vertex(&*VERTEX).unwrap()
-        .with_fragment(&*FRAGMENT).unwrap();
 
-    static ref SHADER_REFLECTION: SpirvReflection = SHADERS.reflect().unwrap();
+    let parameters = block
+        .members
+        .iter()
+        .map(|member| {
+            let flags = member
+                .type_description
+                .as_ref()
+                .map_or(ReflectTypeFlags::UNDEFINED, |ty| ty.type_flags);
+            let ty = if flags == ReflectTypeFlags::FLOAT && member.numeric.scalar.width == 32 {
+                ParameterType::Float
+            } else if flags == ReflectTypeFlags::FLOAT | ReflectTypeFlags::VECTOR && member.numeric.scalar.width == 32 {
+                match member.numeric.vector.component_count {
+                    2 => ParameterType::Vec2,
+                    3 => ParameterType::Vec3,
+                    _ => ParameterType::Vec4,
+                }
+            } else {
+                panic!("Parameter {} of {} isn't a float or a float vector.", member.name, name);
+            };
+
+            Parameter {
+                name: member.name.clone(),
+                offset: member.offset,
+                ty,
+            }
+        })
+        .collect();
+
+    (parameters, block.size)
+}
+
+/// Where a pass looks up the values of its parameters every frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParameterSource {
+    /// Effect at this position of `RenderSettings::effects`.
+    Chain(usize),
+    /// `RenderSettings::bloom`.
+    Bloom,
+    /// Passes without parameters, such as anti-aliasing.
+    None,
 }
 
-#[derive(Debug, Default)]
-pub struct PipelineDesc;
+impl ParameterSource {
+    fn get<T: PostEffectAux + ?Sized>(self, aux: &T, name: &str) -> Option<ParameterValue> {
+        match self {
+            ParameterSource::Chain(index) => aux.settings().effects.get(index)?.parameter(name),
+            ParameterSource::Bloom => aux.settings().bloom.as_ref()?.parameter(name),
+            ParameterSource::None => None,
+        }
+    }
+}
+
+/// Shaders as they were when the graph was built, later changes need a rebuild.
+#[derive(Debug)]
+pub struct PipelineDesc {
+    shaders: ShaderSetBuilder,
+    reflection: SpirvReflection,
+    source: ParameterSource,
+    parameters: Vec<Parameter>,
+    parameters_size: u32,
+    reads_luminance: bool,
+    blend: Option<hal::pso::BlendState>,
+    filter: Filter,
+}
+
+impl PipelineDesc {
+    /// Panics if `effect` sets parameters its shader doesn't have.
+    ///
+    /// The pass blends onto its target by alpha and samples the nearest texel of `source`.
+    pub fn new(effect: &PostEffect, source: ParameterSource) -> Self {
+        let defines: Vec<_> = effect
+            .defines
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect();
+
+        let fragment = shaders::get(&effect.shader, &defines);
+        let (parameters, parameters_size) = reflect_parameters(&effect.shader, &fragment);
+        for (name, value) in &effect.parameters {
+            match parameters.iter().find(|parameter| &parameter.name == name) {
+                Some(parameter) => assert_eq!(
+                    parameter.ty,
+                    value.ty(),
+                    "Parameter {} of {} has a different type.",
+                    name,
+                    effect.shader
+                ),
+                None => panic!("{} has no parameter {}", effect.shader, name),
+            }
+        }
+
+        let shaders = ShaderSetBuilder::default()
+            .with_vertex(&shaders::get("fullscreen_triangle.vert", &[])).unwrap()
+            .with_fragment(&fragment).unwrap();
+        let reflection = shaders.reflect().unwrap();
+        let reads_luminance = reflection
+            .descriptor_sets
+            .first()
+            .is_some_and(|set| set.iter().any(|binding| binding.binding == 1));
+        Self {
+            shaders,
+            reflection,
+            source,
+            parameters,
+            parameters_size,
+            reads_luminance,
+            blend: Some(hal::pso::BlendState::ALPHA),
+            filter: Filter::Nearest,
+        }
+    }
+
+    /// Blending onto the target, `None` replaces its contents.
+    pub fn with_blend(mut self, blend: Option<hal::pso::BlendState>) -> Self {
+        self.blend = blend;
+        self
+    }
+
+    /// Filter `source` is sampled with.
+    pub fn with_filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Whether the pass needs the luminance buffer, see `luminance::LuminanceDesc`.
+    pub fn reads_luminance(&self) -> bool {
+        self.reads_luminance
+    }
+}
 
 #[derive(Debug)]
 pub struct Pipeline<B: hal::Backend> {
+    source: ParameterSource,
+    parameters: Vec<Parameter>,
+    /// Block of push constants, rewritten from the parameters every frame.
+    push_constants: Vec<u32>,
     sets: Vec<B::DescriptorSet>,
     descriptor_pool: B::DescriptorPool,
     image_sampler: Escape<Sampler<B>>,
     image_view: Escape<ImageView<B>>
 }
-impl<B> SimpleGraphicsPipelineDesc<B, Aux<B>> for PipelineDesc
+impl<B, T> SimpleGraphicsPipelineDesc<B, T> for PipelineDesc
 where
     B: hal::Backend,
+    T: PostEffectAux + ?Sized,
 {
     type Pipeline = Pipeline<B>;
 
+    fn buffers(&self) -> Vec<BufferAccess> {
+        if !self.reads_luminance {
+            return Vec::new();
+        }
+        vec![BufferAccess {
+            access: hal::buffer::Access::SHADER_READ,
+            usage: hal::buffer::Usage::STORAGE,
+            stages: hal::pso::PipelineStage::FRAGMENT_SHADER,
+        }]
+    }
+
     fn images(&self) -> Vec<ImageAccess> {
         vec![ImageAccess {
             access: hal::image::Access::SHADER_READ,
@@ -70,36 +333,23 @@ where
         }]
     }
 
+    fn colors(&self) -> Vec<hal::pso::ColorBlendDesc> {
+        vec![hal::pso::ColorBlendDesc {
+            mask: hal::pso::ColorMask::ALL,
+            blend: self.blend,
+        }]
+    }
+
     fn depth_stencil(&self) -> Option<hal::pso::DepthStencilDesc> {
         None
     }
 
-    fn load_shader_set(&self, factory: &mut Factory<B>, _aux: &Aux<B>) -> ShaderSet<B> {
-        SHADERS.build(factory, Default::default()).unwrap()
+    fn load_shader_set(&self, factory: &mut Factory<B>, _aux: &T) -> ShaderSet<B> {
+        self.shaders.build(factory, Default::default()).unwrap()
     }
 
     fn layout(&self) -> Layout {
-        Layout {
-            sets: vec![SetLayout {
-                bindings: vec![
-                    hal::pso::DescriptorSetLayoutBinding {
-                        binding: 0,
-                        ty: hal::pso::DescriptorType::Sampler,
-                        count: 1,
-                        stage_flags: hal::pso::ShaderStageFlags::FRAGMENT,
-                        immutable_samplers: false,
-                    },
-                    hal::pso::DescriptorSetLayoutBinding {
-                        binding: 1,
-                        ty: hal::pso::DescriptorType::SampledImage,
-                        count: 1,
-                        stage_flags: hal::pso::ShaderStageFlags::FRAGMENT,
-                        immutable_samplers: false,
-                    }
-                ],
-            }],
-            push_constants: Vec::new(),
-        }
+        self.reflection.layout().unwrap()
     }
 
     fn build(
@@ -107,33 +357,35 @@ where
         ctx: &GraphContext<B>,
         factory: &mut Factory<B>,
         _queue: QueueId,
-        _aux: &Aux<B>,
-        _buffers: Vec<NodeBuffer>,
+        _aux: &T,
+        buffers: Vec<NodeBuffer>,
         images: Vec<NodeImage>,
         set_layouts: &[Handle<DescriptorSetLayout<B>>],
     ) -> Result<Self::Pipeline, hal::pso::CreationError> {
         
         let frames = ctx.frames_in_flight as usize;
 
+        let mut ranges = vec![hal::pso::DescriptorRangeDesc {
+            ty: hal::pso::DescriptorType::CombinedImageSampler,
+            count: frames,
+        }];
+        if self.reads_luminance {
+            ranges.push(hal::pso::DescriptorRangeDesc {
+                ty: hal::pso::DescriptorType::StorageBuffer,
+                count: frames,
+            });
+        }
+
         let mut descriptor_pool = unsafe {
             factory.create_descriptor_pool(
                 frames,
-                vec![
-                    hal::pso::DescriptorRangeDesc {
-                        ty: hal::pso::DescriptorType::Sampler,
-                        count: frames,
-                    },
-                    hal::pso::DescriptorRangeDesc {
-                        ty: hal::pso::DescriptorType::SampledImage,
-                        count: frames,
-                    },
-                ],
+                ranges,
                 hal::pso::DescriptorPoolCreateFlags::empty(),
             )?
         };
         
         let image_sampler = factory
-            .create_sampler(SamplerDesc::new(Filter::Nearest, WrapMode::Clamp))
+            .create_sampler(SamplerDesc::new(self.filter, WrapMode::Clamp))
             .unwrap();
             
         let image_handle = ctx
@@ -145,7 +397,7 @@ where
                 image_handle.clone(),
                 ImageViewInfo {
                     view_kind: ViewKind::D2,
-                    format: hal::format::Format::Rgba32Sfloat,
+                    format: image_handle.format(),
                     swizzle: hal::format::Swizzle::NO,
                     range: images[0].range.clone(),
                 },
@@ -155,29 +407,34 @@ where
         let mut sets = Vec::with_capacity(frames);
         for _ in 0..frames {
             unsafe {
-                let set = descriptor_pool.allocate_set(&set_layouts[0].raw()).unwrap();
-                factory.write_descriptor_sets(vec![
-                    hal::pso::DescriptorSetWrite {
-                        set: &set,
-                        binding: 0,
-                        array_offset: 0,
-                        descriptors: Some(hal::pso::Descriptor::Sampler(image_sampler.raw())),
-                    },
-                    hal::pso::DescriptorSetWrite {
+                let set = descriptor_pool.allocate_set(set_layouts[0].raw()).unwrap();
+                factory.write_descriptor_sets(Some(hal::pso::DescriptorSetWrite {
+                    set: &set,
+                    binding: 0,
+                    array_offset: 0,
+                    descriptors: Some(hal::pso::Descriptor::CombinedImageSampler(
+                        image_view.raw(),
+                        hal::image::Layout::ShaderReadOnlyOptimal,
+                        image_sampler.raw(),
+                    )),
+                }));
+                if let Some(buffer) = buffers.first() {
+                    let buffer = ctx.get_buffer(buffer.id).expect("No luminance buffer supplied.");
+                    factory.write_descriptor_sets(Some(hal::pso::DescriptorSetWrite {
                         set: &set,
                         binding: 1,
                         array_offset: 0,
-                        descriptors: Some(hal::pso::Descriptor::Image(
-                            image_view.raw(),
-                            hal::image::Layout::ShaderReadOnlyOptimal,
-                        )),
-                    }
-                ]);
+                        descriptors: Some(hal::pso::Descriptor::Buffer(buffer.raw(), None..None)),
+                    }));
+                }
                 sets.push(set);
             }
         }
 
         Ok( Pipeline {
+            source: self.source,
+            parameters: self.parameters,
+            push_constants: vec![0; (self.parameters_size as usize).div_ceil(4)],
             sets,
             image_view,
             image_sampler,
@@ -186,9 +443,10 @@ where
     }
 }
 
-impl<B> SimpleGraphicsPipeline<B, Aux<B>> for Pipeline<B>
+impl<B, T> SimpleGraphicsPipeline<B, T> for Pipeline<B>
 where
     B: hal::Backend,
+    T: PostEffectAux + ?Sized,
 {
     type Desc = PipelineDesc;
 
@@ -197,9 +455,31 @@ where
         layout: &<B as hal::Backend>::PipelineLayout,
         mut encoder: RenderPassEncoder<B>,
         index: usize,
-        aux: &Aux<B>,
+        aux: &T,
     ) {
+        // values changed to another type since the graph was built are left at zero
+        for parameter in &self.parameters {
+            let value = self
+                .source
+                .get(aux, &parameter.name)
+                .filter(|value| value.ty() == parameter.ty);
+            let components = value.as_ref().map_or(&[0.; 4][..], ParameterValue::components);
+            let start = parameter.offset as usize / 4;
+            let words = &mut self.push_constants[start..start + parameter.ty.components()];
+            for (word, component) in words.iter_mut().zip(components) {
+                *word = component.to_bits();
+            }
+        }
+
         unsafe {
+            if !self.push_constants.is_empty() {
+                encoder.push_constants(
+                    layout,
+                    hal::pso::ShaderStageFlags::FRAGMENT,
+                    0,
+                    &self.push_constants,
+                );
+            }
             encoder.bind_graphics_descriptor_sets(
                 layout,
                 0,
@@ -210,11 +490,13 @@ where
         }
     }
 
-    fn dispose(mut self, factory: &mut Factory<B>, aux: &Aux<B>) {
+    fn dispose(mut self, factory: &mut Factory<B>, _aux: &T) {
         unsafe {
             self.descriptor_pool.reset();
             factory.destroy_descriptor_pool(self.descriptor_pool);
         }
+        drop(self.image_view);
+        drop(self.image_sampler);
     }
 }
 
@@ -0,0 +1,157 @@
+use rendy::{
+    command::{
+        CommandBuffer, CommandPool, ExecutableState, Family, Graphics, MultiShot, PendingState,
+        SimultaneousUse, Submit,
+    },
+    factory::Factory,
+    frame::Frames,
+    graph::{
+        gfx_acquire_barriers, gfx_release_barriers, GraphContext, ImageAccess, Node, NodeBuffer,
+        NodeBuildError, NodeDesc, NodeImage, NodeSubmittable,
+    },
+    hal,
+};
+
+/// Resolves its multisampled first image into its single sampled second one.
+#[derive(Debug, Default)]
+pub struct ResolveDesc;
+
+#[derive(Debug)]
+pub struct Resolve<B: hal::Backend> {
+    command_pool: CommandPool<B, Graphics>,
+    command_buffer:
+        CommandBuffer<B, Graphics, PendingState<ExecutableState<MultiShot<SimultaneousUse>>>>,
+    submit: Submit<B, SimultaneousUse>,
+}
+
+impl<'a, B> NodeSubmittable<'a, B> for Resolve<B>
+where
+    B: hal::Backend,
+{
+    type Submittable = &'a Submit<B, SimultaneousUse>;
+    type Submittables = &'a [Submit<B, SimultaneousUse>];
+}
+
+impl<B, T> Node<B, T> for Resolve<B>
+where
+    B: hal::Backend,
+    T: ?Sized,
+{
+    type Capability = Graphics;
+
+    fn run<'a>(
+        &'a mut self,
+        _ctx: &GraphContext<B>,
+        _factory: &Factory<B>,
+        _aux: &T,
+        _frames: &'a Frames<B>,
+    ) -> &'a [Submit<B, SimultaneousUse>] {
+        std::slice::from_ref(&self.submit)
+    }
+
+    unsafe fn dispose(mut self, factory: &mut Factory<B>, _aux: &T) {
+        self.command_pool
+            .free_buffers(Some(self.command_buffer.mark_complete()));
+        factory.destroy_command_pool(self.command_pool);
+    }
+}
+
+impl<B, T> NodeDesc<B, T> for ResolveDesc
+where
+    B: hal::Backend,
+    T: ?Sized,
+{
+    type Node = Resolve<B>;
+
+    fn images(&self) -> Vec<ImageAccess> {
+        vec![
+            ImageAccess {
+                access: hal::image::Access::TRANSFER_READ,
+                usage: hal::image::Usage::TRANSFER_SRC,
+                layout: hal::image::Layout::TransferSrcOptimal,
+                stages: hal::pso::PipelineStage::TRANSFER,
+            },
+            ImageAccess {
+                access: hal::image::Access::TRANSFER_WRITE,
+                usage: hal::image::Usage::TRANSFER_DST,
+                layout: hal::image::Layout::TransferDstOptimal,
+                stages: hal::pso::PipelineStage::TRANSFER,
+            },
+        ]
+    }
+
+    fn build<'a>(
+        self,
+        ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        family: &mut Family<B>,
+        _queue: usize,
+        _aux: &T,
+        buffers: Vec<NodeBuffer>,
+        images: Vec<NodeImage>,
+    ) -> Result<Self::Node, NodeBuildError> {
+        assert!(buffers.is_empty());
+        assert_eq!(images.len(), 2);
+
+        let source = ctx
+            .get_image(images[0].id)
+            .expect("No multisampled image supplied.");
+        let target = ctx
+            .get_image(images[1].id)
+            .expect("No resolve target supplied.");
+        assert_eq!(source.kind().extent(), target.kind().extent());
+
+        let mut command_pool = factory
+            .create_command_pool(family)
+            .map_err(NodeBuildError::OutOfMemory)?
+            .with_capability::<Graphics>()
+            .expect("Graph builder must provide family with Graphics capability");
+        let initial = command_pool.allocate_buffers(1).remove(0);
+        let mut recording = initial.begin(MultiShot(SimultaneousUse), ());
+
+        let layers = hal::image::SubresourceLayers {
+            aspects: hal::format::Aspects::COLOR,
+            level: 0,
+            layers: 0..1,
+        };
+
+        unsafe {
+            {
+                let (stages, barriers) = gfx_acquire_barriers(ctx, None, &images);
+                recording
+                    .encoder()
+                    .pipeline_barrier(stages, hal::memory::Dependencies::empty(), barriers);
+            }
+
+            hal::command::CommandBuffer::resolve_image(
+                recording.raw(),
+                source.raw(),
+                images[0].layout,
+                target.raw(),
+                images[1].layout,
+                Some(hal::command::ImageResolve {
+                    src_subresource: layers.clone(),
+                    src_offset: hal::image::Offset::ZERO,
+                    dst_subresource: layers,
+                    dst_offset: hal::image::Offset::ZERO,
+                    extent: source.kind().extent(),
+                }),
+            );
+
+            {
+                let (stages, barriers) = gfx_release_barriers(ctx, None, &images);
+                recording
+                    .encoder()
+                    .pipeline_barrier(stages, hal::memory::Dependencies::empty(), barriers);
+            }
+        }
+
+        let (submit, command_buffer) = recording.finish().submit();
+
+        Ok(Resolve {
+            command_pool,
+            command_buffer,
+            submit,
+        })
+    }
+}
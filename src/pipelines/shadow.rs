@@ -0,0 +1,253 @@
+use rendy::{
+    command::{QueueId, RenderPassEncoder},
+    factory::Factory,
+    graph::{render::*, GraphContext, NodeBuffer, NodeImage},
+    hal::{self, adapter::PhysicalDevice, device::Device, pso::ShaderStageFlags},
+    mesh::VertexFormat,
+    shader::{ShaderSetBuilder, SpirvReflection},
+};
+
+use super::{vertex_input, MeshAux};
+use crate::shaders;
+
+/// Width and height of the shadow map of each cascade.
+pub const SHADOW_MAP_SIZE: u32 = 2048;
+
+/// Largest width and height of the shadow atlas `hal::pso::Rect`, made of `i16`s, can address.
+pub const MAX_ATLAS_SIZE: u32 = i16::MAX as u32 + 1;
+
+/// Cascades in each row of the shadow atlas, which fills its rows from the top.
+pub fn atlas_columns(cascades: usize) -> usize {
+    (cascades as f32).sqrt().ceil() as usize
+}
+
+/// Size of the shadow atlas holding the shadow maps of `cascades` cascades.
+pub fn atlas_size(cascades: usize) -> [u32; 2] {
+    let columns = atlas_columns(cascades);
+    [columns as u32 * SHADOW_MAP_SIZE, cascades.div_ceil(columns) as u32 * SHADOW_MAP_SIZE]
+}
+
+/// Most cascades whose atlas fits into images of at most `max_size` by `max_size`.
+pub fn max_cascades(max_size: u32) -> usize {
+    // the atlas is as wide as it is high, or one row less
+    let columns = (max_size.min(MAX_ATLAS_SIZE) / SHADOW_MAP_SIZE) as usize;
+    columns * columns
+}
+
+/// Renders the depth of every object as seen from the light of the aux into the shadow atlas,
+/// once for each cascade. Its only image is the atlas it renders to.
+#[derive(Debug)]
+pub struct ShadowDesc {
+    shaders: ShaderSetBuilder,
+    reflection: SpirvReflection,
+    cascades: usize,
+}
+
+impl ShadowDesc {
+    pub fn new(cascades: usize) -> Self {
+        let shaders = ShaderSetBuilder::default()
+            .with_vertex(&shaders::get("shadow.vert", &[])).unwrap();
+        let reflection = shaders.reflect().unwrap();
+        Self {
+            shaders,
+            reflection,
+            cascades,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Shadow<B: hal::Backend> {
+    layout: B::PipelineLayout,
+    graphics_pipeline: B::GraphicsPipeline,
+    /// Only the positions, see `mesh::PipelineDesc::vertex_formats`.
+    vertex_formats: Vec<VertexFormat>,
+    cascades: usize,
+}
+
+impl<B, T> RenderGroupDesc<B, T> for ShadowDesc
+where
+    B: hal::Backend,
+    T: MeshAux<B> + ?Sized,
+{
+    fn colors(&self) -> usize {
+        0
+    }
+
+    fn build<'a>(
+        self,
+        _ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        _queue: QueueId,
+        _aux: &T,
+        _framebuffer_width: u32,
+        _framebuffer_height: u32,
+        subpass: hal::pass::Subpass<'_, B>,
+        _buffers: Vec<NodeBuffer>,
+        _images: Vec<NodeImage>,
+    ) -> Result<Box<dyn RenderGroup<B, T>>, hal::pso::CreationError> {
+        let layout = self.reflection.layout().unwrap();
+        let pipeline_layout = unsafe {
+            factory
+                .device()
+                .create_pipeline_layout(std::iter::empty::<&B::DescriptorSetLayout>(), layout.push_constants)
+                .map_err(hal::pso::CreationError::OutOfMemory)?
+        };
+
+        let vertex_formats = vec![self.reflection.attributes(&["position"]).unwrap()];
+        let (vertex_buffers, attributes) = vertex_input(&self.reflection, &vertex_formats);
+
+        let mut shader_set = self.shaders.build(factory, Default::default()).unwrap();
+        let graphics_pipeline = unsafe {
+            factory.device().create_graphics_pipeline(
+                &hal::pso::GraphicsPipelineDesc {
+                    shaders: shader_set.raw().unwrap(),
+                    rasterizer: hal::pso::Rasterizer {
+                        // occluders between the light and the cascade are flattened onto its near
+                        // plane instead of being clipped, so they still cast their shadows
+                        depth_clamping: factory
+                            .physical()
+                            .features()
+                            .contains(hal::Features::DEPTH_CLAMP),
+                        ..hal::pso::Rasterizer::FILL
+                    },
+                    vertex_buffers,
+                    attributes,
+                    input_assembler: hal::pso::InputAssemblerDesc {
+                        primitive: hal::pso::Primitive::TriangleList,
+                        with_adjacency: false,
+                        restart_index: None,
+                    },
+                    blender: hal::pso::BlendDesc {
+                        logic_op: None,
+                        targets: Vec::new(),
+                    },
+                    depth_stencil: hal::pso::DepthStencilDesc {
+                        depth: Some(hal::pso::DepthTest {
+                            fun: hal::pso::Comparison::Less,
+                            write: true,
+                        }),
+                        depth_bounds: false,
+                        stencil: None,
+                    },
+                    multisampling: None,
+                    // each cascade has its own part of the atlas
+                    baked_states: hal::pso::BakedStates::default(),
+                    layout: &pipeline_layout,
+                    subpass,
+                    flags: hal::pso::PipelineCreationFlags::empty(),
+                    parent: hal::pso::BasePipeline::None,
+                },
+                None,
+            )
+        };
+        shader_set.dispose(factory);
+        let graphics_pipeline = graphics_pipeline?;
+
+        Ok(Box::new(Shadow {
+            layout: pipeline_layout,
+            graphics_pipeline,
+            vertex_formats,
+            cascades: self.cascades,
+        }))
+    }
+}
+
+impl<B, T> RenderGroup<B, T> for Shadow<B>
+where
+    B: hal::Backend,
+    T: MeshAux<B> + ?Sized,
+{
+    fn prepare(
+        &mut self,
+        _factory: &Factory<B>,
+        _queue: QueueId,
+        _index: usize,
+        _subpass: hal::pass::Subpass<'_, B>,
+        _aux: &T,
+    ) -> PrepareResult {
+        PrepareResult::DrawRecord
+    }
+
+    fn draw_inline(
+        &mut self,
+        mut encoder: RenderPassEncoder<'_, B>,
+        _index: usize,
+        _subpass: hal::pass::Subpass<'_, B>,
+        aux: &T,
+    ) {
+        let instances = aux.scene().instances();
+        let transforms = aux.light().get_cascade_transforms(aux.camera(), self.cascades);
+        let columns = atlas_columns(self.cascades);
+        encoder.bind_graphics_pipeline(&self.graphics_pipeline);
+        for (cascade, light_transform) in transforms.iter().enumerate() {
+            let rect = hal::pso::Rect {
+                x: ((cascade % columns) as u32 * SHADOW_MAP_SIZE) as i16,
+                y: ((cascade / columns) as u32 * SHADOW_MAP_SIZE) as i16,
+                w: SHADOW_MAP_SIZE as i16,
+                h: SHADOW_MAP_SIZE as i16,
+            };
+            unsafe {
+                encoder.set_viewports(0, Some(&hal::pso::Viewport {
+                    rect,
+                    depth: 0.0..1.0,
+                }));
+                encoder.set_scissors(0, Some(&rect));
+            }
+
+            for instance in &instances {
+                let transform = light_transform * instance.model;
+                unsafe {
+                    let data = std::slice::from_raw_parts(
+                        transform.as_ptr() as *const u32,
+                        16,
+                    );
+                    encoder.push_constants(&self.layout, ShaderStageFlags::VERTEX, 0, data);
+                }
+                aux.meshes()[instance.mesh].bind_and_draw(0, &self.vertex_formats, 0..1, &mut encoder).unwrap();
+            }
+        }
+    }
+
+    fn dispose(self: Box<Self>, factory: &mut Factory<B>, _aux: &T) {
+        unsafe {
+            factory.device().destroy_graphics_pipeline(self.graphics_pipeline);
+            factory.device().destroy_pipeline_layout(self.layout);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn atlas_fills_rows_of_a_square_grid() {
+        let maps = |cascades| {
+            let [width, height] = atlas_size(cascades);
+            (width / SHADOW_MAP_SIZE, height / SHADOW_MAP_SIZE)
+        };
+        assert_eq!(maps(1), (1, 1));
+        assert_eq!(maps(2), (2, 1));
+        assert_eq!(maps(3), (2, 2));
+        assert_eq!(maps(4), (2, 2));
+        assert_eq!(maps(5), (3, 2));
+        assert_eq!(maps(7), (3, 3));
+    }
+
+    #[test]
+    fn max_cascades_are_the_most_that_fit() {
+        for &max_size in &[4096, 8192, 10000, 16384, MAX_ATLAS_SIZE, 65536] {
+            let columns = (max_size.min(MAX_ATLAS_SIZE) / SHADOW_MAP_SIZE) as usize;
+            let max = max_cascades(max_size);
+            assert_eq!(max, columns * columns);
+
+            let [width, height] = atlas_size(max);
+            assert!(width.max(height) <= max_size.min(MAX_ATLAS_SIZE), "{}", max_size);
+            let [width, height] = atlas_size(max + 1);
+            assert!(width.max(height) > max_size.min(MAX_ATLAS_SIZE), "{}", max_size);
+        }
+        assert_eq!(max_cascades(SHADOW_MAP_SIZE), 1);
+        assert_eq!(max_cascades(MAX_ATLAS_SIZE), 256);
+    }
+}
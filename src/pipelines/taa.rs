@@ -0,0 +1,434 @@
+use rendy::{
+    command::{
+        CommandPool, Compute, Family, IndividualReset, MultiShot, NoSimultaneousUse, Submit,
+    },
+    factory::Factory,
+    frame::{cirque::CommandCirque, Frames},
+    graph::{
+        gfx_acquire_barriers, gfx_release_barriers, GraphContext, ImageAccess, Node, NodeBuffer,
+        NodeBuildError, NodeDesc, NodeImage, NodeSubmittable,
+    },
+    hal::{self, device::Device},
+    memory::Data,
+    resource::{
+        DescriptorSet, DescriptorSetLayout, Escape, Filter, Handle, Image, ImageInfo, ImageView,
+        ImageViewInfo, Sampler, SamplerDesc, ViewKind, WrapMode,
+    },
+    shader::{Shader, ShaderSetBuilder, SpirvShader},
+};
+
+use nalgebra::Vector2;
+
+use crate::shaders;
+
+/// Frames the jitter takes to repeat.
+const JITTER_FRAMES: usize = 8;
+
+/// Offset of the camera in normalized device coordinates for `frame`, spread over
+/// a pixel of an image of `size` by a Halton sequence.
+pub fn jitter(frame: usize, size: [u32; 2]) -> Vector2<f32> {
+    fn halton(mut index: usize, base: usize) -> f32 {
+        let (mut result, mut fraction) = (0., 1.);
+        while index > 0 {
+            fraction /= base as f32;
+            result += fraction * (index % base) as f32;
+            index /= base;
+        }
+        result
+    }
+
+    // the sequence starts at zero, which would sample the pixel corner
+    let index = frame % JITTER_FRAMES + 1;
+    Vector2::new(
+        (halton(index, 2) - 0.5) * 2. / size[0] as f32,
+        (halton(index, 3) - 0.5) * 2. / size[1] as f32,
+    )
+}
+
+/// Blends its first image with the history of the frames before, reprojected by the velocity
+/// in its second image, and writes the result to its third image.
+///
+/// Images of the graph start out undefined every frame, so the node keeps the history in
+/// images of its own. It is only kept as long as the graph is.
+#[derive(Debug)]
+pub struct TaaDesc {
+    shader: SpirvShader,
+}
+
+impl Default for TaaDesc {
+    fn default() -> Self {
+        Self {
+            shader: shaders::get("taa.comp", &[]),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Taa<B: hal::Backend> {
+    command_pool: CommandPool<B, Compute, IndividualReset>,
+    command_cirque: CommandCirque<B, Compute>,
+    layout: B::PipelineLayout,
+    pipeline: B::ComputePipeline,
+    /// Two images taking turns, one holds the last frame while the other gets this one.
+    history: Vec<Handle<Image<B>>>,
+    history_views: Vec<Escape<ImageView<B>>>,
+    /// `sets[i]` reads `history[i]` and writes the other one.
+    sets: Vec<Escape<DescriptorSet<B>>>,
+    nearest_sampler: Escape<Sampler<B>>,
+    linear_sampler: Escape<Sampler<B>>,
+    image_views: Vec<Escape<ImageView<B>>>,
+    images: Vec<NodeImage>,
+    /// Workgroups covering the image, one per 16x16 pixels.
+    groups: [u32; 2],
+    /// Frames recorded so far, the first one has no history.
+    frame: usize,
+}
+
+impl<'a, B> NodeSubmittable<'a, B> for Taa<B>
+where
+    B: hal::Backend,
+{
+    type Submittable = Submit<B, NoSimultaneousUse>;
+    type Submittables = Option<Submit<B, NoSimultaneousUse>>;
+}
+
+impl<B, T> Node<B, T> for Taa<B>
+where
+    B: hal::Backend,
+    T: ?Sized,
+{
+    type Capability = Compute;
+
+    fn run<'a>(
+        &'a mut self,
+        ctx: &GraphContext<B>,
+        _factory: &Factory<B>,
+        _aux: &T,
+        frames: &'a Frames<B>,
+    ) -> Option<Submit<B, NoSimultaneousUse>> {
+        let read = self.frame % 2;
+        let reset = self.frame == 0;
+        self.frame += 1;
+
+        let Self {
+            command_pool,
+            command_cirque,
+            layout,
+            pipeline,
+            history,
+            sets,
+            images,
+            groups,
+            ..
+        } = self;
+
+        let whole = hal::image::SubresourceRange {
+            aspects: hal::format::Aspects::COLOR,
+            levels: 0..1,
+            layers: 0..1,
+        };
+        // the history is always in the general layout, only its first use needs a transition
+        let from = if reset {
+            (hal::image::Access::empty(), hal::image::Layout::Undefined)
+        } else {
+            (hal::image::Access::SHADER_WRITE, hal::image::Layout::General)
+        };
+        let history_barriers = [
+            hal::memory::Barrier::Image {
+                states: from..(hal::image::Access::SHADER_READ, hal::image::Layout::General),
+                families: None,
+                target: history[read].raw(),
+                range: whole.clone(),
+            },
+            hal::memory::Barrier::Image {
+                states: (hal::image::Access::empty(), from.1)
+                    ..(hal::image::Access::SHADER_WRITE, hal::image::Layout::General),
+                families: None,
+                target: history[1 - read].raw(),
+                range: whole,
+            },
+        ];
+
+        // recorded every frame, the history images swap
+        let submit = command_cirque.encode(frames, command_pool, |cbuf| {
+            cbuf.or_reset(|cbuf| cbuf.reset()).init(|cbuf| {
+                let mut cbuf = cbuf.begin(MultiShot(NoSimultaneousUse), ());
+
+                unsafe {
+                    {
+                        let (stages, barriers) = gfx_acquire_barriers(ctx, None, &*images);
+                        let mut encoder = cbuf.encoder();
+                        encoder.pipeline_barrier(stages, hal::memory::Dependencies::empty(), barriers);
+                        encoder.pipeline_barrier(
+                            hal::pso::PipelineStage::COMPUTE_SHADER..hal::pso::PipelineStage::COMPUTE_SHADER,
+                            hal::memory::Dependencies::empty(),
+                            history_barriers,
+                        );
+                        encoder.bind_compute_pipeline(pipeline);
+                        encoder.bind_compute_descriptor_sets(layout, 0, Some(sets[read].raw()), std::iter::empty());
+                    }
+
+                    // the encoder only pushes constants for graphics pipelines
+                    hal::command::CommandBuffer::push_compute_constants(
+                        cbuf.raw(),
+                        layout,
+                        0,
+                        &[reset as u32],
+                    );
+
+                    let mut encoder = cbuf.encoder();
+                    encoder.dispatch(groups[0], groups[1], 1);
+                    let (stages, barriers) = gfx_release_barriers(ctx, None, &*images);
+                    encoder.pipeline_barrier(stages, hal::memory::Dependencies::empty(), barriers);
+                }
+
+                cbuf.finish()
+            })
+        });
+
+        Some(submit)
+    }
+
+    unsafe fn dispose(mut self, factory: &mut Factory<B>, _aux: &T) {
+        let pool = &mut self.command_pool;
+        self.command_cirque.dispose(|buffer| {
+            buffer.either_with(
+                &mut *pool,
+                |pool, executable| pool.free_buffers(Some(executable)),
+                |pool, pending| pool.free_buffers(Some(pending.mark_complete())),
+            );
+        });
+        factory.destroy_command_pool(self.command_pool);
+
+        factory.device().destroy_compute_pipeline(self.pipeline);
+        factory.device().destroy_pipeline_layout(self.layout);
+        drop(self.sets);
+        drop(self.image_views);
+        drop(self.history_views);
+        drop(self.history);
+        drop(self.nearest_sampler);
+        drop(self.linear_sampler);
+    }
+}
+
+impl<B, T> NodeDesc<B, T> for TaaDesc
+where
+    B: hal::Backend,
+    T: ?Sized,
+{
+    type Node = Taa<B>;
+
+    fn images(&self) -> Vec<ImageAccess> {
+        let sampled = ImageAccess {
+            access: hal::image::Access::SHADER_READ,
+            usage: hal::image::Usage::SAMPLED,
+            layout: hal::image::Layout::ShaderReadOnlyOptimal,
+            stages: hal::pso::PipelineStage::COMPUTE_SHADER,
+        };
+        vec![
+            sampled,
+            sampled,
+            ImageAccess {
+                access: hal::image::Access::SHADER_WRITE,
+                usage: hal::image::Usage::STORAGE,
+                layout: hal::image::Layout::General,
+                stages: hal::pso::PipelineStage::COMPUTE_SHADER,
+            },
+        ]
+    }
+
+    fn build<'a>(
+        self,
+        ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        family: &mut Family<B>,
+        _queue: usize,
+        _aux: &T,
+        buffers: Vec<NodeBuffer>,
+        images: Vec<NodeImage>,
+    ) -> Result<Self::Node, NodeBuildError> {
+        assert!(buffers.is_empty());
+        assert_eq!(images.len(), 3);
+
+        let mut reflection = ShaderSetBuilder::default()
+            .with_compute(&self.shader).unwrap()
+            .reflect()
+            .unwrap();
+        let set_layout: Handle<DescriptorSetLayout<B>> = factory
+            .create_descriptor_set_layout(reflection.layout().unwrap().sets.remove(0).bindings)
+            .map_err(NodeBuildError::OutOfMemory)?
+            .into();
+        let layout = unsafe {
+            factory
+                .device()
+                .create_pipeline_layout(Some(set_layout.raw()), reflection.push_constants.drain(..))
+                .map_err(NodeBuildError::OutOfMemory)?
+        };
+
+        let pipeline = unsafe {
+            let module = self.shader.module(factory).unwrap();
+            let pipeline = factory.device().create_compute_pipeline(
+                &hal::pso::ComputePipelineDesc::new(
+                    hal::pso::EntryPoint {
+                        entry: self.shader.entry(),
+                        module: &module,
+                        specialization: hal::pso::Specialization::default(),
+                    },
+                    &layout,
+                ),
+                None,
+            );
+            factory.device().destroy_shader_module(module);
+            pipeline.map_err(NodeBuildError::Pipeline)?
+        };
+
+        let image_views = images
+            .iter()
+            .map(|node_image| {
+                let image = ctx
+                    .get_image(node_image.id)
+                    .expect("No input image supplied.");
+                factory
+                    .create_image_view(
+                        image.clone(),
+                        ImageViewInfo {
+                            view_kind: ViewKind::D2,
+                            format: image.format(),
+                            swizzle: hal::format::Swizzle::NO,
+                            range: node_image.range.clone(),
+                        },
+                    )
+                    .expect("Could not create image view")
+            })
+            .collect::<Vec<_>>();
+
+        let target = ctx
+            .get_image(images[2].id)
+            .expect("No target image supplied.");
+        let extent = target.kind().extent();
+
+        let history: Vec<Handle<Image<B>>> = (0..2)
+            .map(|_| {
+                factory
+                    .create_image(
+                        ImageInfo {
+                            kind: target.kind(),
+                            levels: 1,
+                            format: target.format(),
+                            tiling: hal::image::Tiling::Optimal,
+                            view_caps: hal::image::ViewCapabilities::empty(),
+                            usage: hal::image::Usage::SAMPLED | hal::image::Usage::STORAGE,
+                        },
+                        Data,
+                    )
+                    .expect("Could not create history image")
+                    .into()
+            })
+            .collect();
+        let history_views: Vec<_> = history
+            .iter()
+            .map(|image| {
+                factory
+                    .create_image_view(
+                        image.clone(),
+                        ImageViewInfo {
+                            view_kind: ViewKind::D2,
+                            format: image.format(),
+                            swizzle: hal::format::Swizzle::NO,
+                            range: hal::image::SubresourceRange {
+                                aspects: hal::format::Aspects::COLOR,
+                                levels: 0..1,
+                                layers: 0..1,
+                            },
+                        },
+                    )
+                    .expect("Could not create image view")
+            })
+            .collect();
+
+        let nearest_sampler = factory
+            .create_sampler(SamplerDesc::new(Filter::Nearest, WrapMode::Clamp))
+            .unwrap();
+        let linear_sampler = factory
+            .create_sampler(SamplerDesc::new(Filter::Linear, WrapMode::Clamp))
+            .unwrap();
+
+        let sets = (0..2)
+            .map(|read| {
+                let set = factory
+                    .create_descriptor_set(set_layout.clone())
+                    .map_err(NodeBuildError::OutOfMemory)?;
+                fn sampled<'a, B: hal::Backend>(
+                    view: &'a ImageView<B>,
+                    layout: hal::image::Layout,
+                    sampler: &'a Sampler<B>,
+                ) -> Option<hal::pso::Descriptor<'a, B>> {
+                    Some(hal::pso::Descriptor::CombinedImageSampler(view.raw(), layout, sampler.raw()))
+                }
+                let write = |binding, descriptors| hal::pso::DescriptorSetWrite {
+                    set: set.raw(),
+                    binding,
+                    array_offset: 0,
+                    descriptors,
+                };
+                let read_only = hal::image::Layout::ShaderReadOnlyOptimal;
+                let general = hal::image::Layout::General;
+                unsafe {
+                    factory.write_descriptor_sets(vec![
+                        write(0, sampled(&image_views[0], read_only, &nearest_sampler)),
+                        write(1, sampled(&image_views[1], read_only, &nearest_sampler)),
+                        write(2, sampled(&history_views[read], general, &linear_sampler)),
+                        write(3, Some(hal::pso::Descriptor::Image(image_views[2].raw(), general))),
+                        write(4, Some(hal::pso::Descriptor::Image(history_views[1 - read].raw(), general))),
+                    ]);
+                }
+                Ok(set)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let command_pool = factory
+            .create_command_pool(family)
+            .map_err(NodeBuildError::OutOfMemory)?
+            .with_capability::<Compute>()
+            .expect("Graph builder must provide family with Compute capability");
+
+        Ok(Taa {
+            command_pool,
+            command_cirque: CommandCirque::new(),
+            layout,
+            pipeline,
+            history,
+            history_views,
+            sets,
+            nearest_sampler,
+            linear_sampler,
+            image_views,
+            images,
+            groups: [extent.width.div_ceil(16), extent.height.div_ceil(16)],
+            frame: 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jitter_stays_within_a_pixel_and_repeats() {
+        let size = [1280, 720];
+        let pixels: Vec<_> = (0..JITTER_FRAMES)
+            .map(|frame| {
+                let offset = jitter(frame, size);
+                // a pixel spans 2 / size in normalized device coordinates
+                Vector2::new(offset.x * size[0] as f32, offset.y * size[1] as f32) / 2.
+            })
+            .collect();
+
+        for (frame, offset) in pixels.iter().enumerate() {
+            assert!(offset.x.abs() <= 0.5 && offset.y.abs() <= 0.5, "{}: {}", frame, offset);
+            assert!(!pixels[..frame].contains(offset), "{} repeats early", frame);
+            assert_eq!(jitter(frame + JITTER_FRAMES, size), jitter(frame, size));
+        }
+    }
+}
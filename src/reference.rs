@@ -0,0 +1,1022 @@
+//! CPU implementation of the shadow, mesh, post effect and anti-aliasing passes.
+//!
+//! Mirrors `shadow.vert`, `mesh.vert`/`mesh.frag` and the fullscreen passes after it closely enough to render the same
+//! images without a GPU, following Vulkan conventions: clip space depth is `0..w`,
+//! `y` points down and pixels are sampled at their centers, or at the standard sample
+//! locations with multisampling.
+
+use nalgebra::{Matrix3, Matrix4, Vector2, Vector3, Vector4};
+
+use crate::{
+    geometry::MeshData,
+    pipelines::{
+        antialiasing::AntiAliasing,
+        bloom::Bloom,
+        post_effect::{ParameterValue, PostEffect},
+        shadow::SHADOW_MAP_SIZE,
+    },
+    texture::TextureData,
+    Camera, DirectionalLight, RenderSettings, Scene,
+};
+
+/// Clear value of the `hdr` and `color` images.
+pub const CLEAR_COLOR: [f32; 4] = [0.1, 0.3, 0.4, 1.0];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Framebuffer {
+    pub size: [u32; 2],
+    /// Rows from top to bottom.
+    pub pixels: Vec<[f32; 4]>,
+}
+
+impl Framebuffer {
+    pub fn new(size: [u32; 2], clear: [f32; 4]) -> Self {
+        Self {
+            size,
+            pixels: vec![clear; size[0] as usize * size[1] as usize],
+        }
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> [f32; 4] {
+        self.pixels[(y * self.size[0] + x) as usize]
+    }
+
+    /// Bilinear sample with clamped coordinates, like the linear samplers of the bloom passes.
+    pub fn sample(&self, uv: [f32; 2]) -> [f32; 4] {
+        let [width, height] = self.size;
+        let x = uv[0] * width as f32 - 0.5;
+        let y = uv[1] * height as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+
+        let texel = |x: f32, y: f32| {
+            let x = (x.max(0.) as u32).min(width - 1);
+            let y = (y.max(0.) as u32).min(height - 1);
+            self.get(x, y)
+        };
+
+        let (a, b) = (texel(x0, y0), texel(x0 + 1., y0));
+        let (c, d) = (texel(x0, y0 + 1.), texel(x0 + 1., y0 + 1.));
+        let mut color = [0.; 4];
+        for i in 0..4 {
+            let top = a[i] + (b[i] - a[i]) * fx;
+            let bottom = c[i] + (d[i] - c[i]) * fx;
+            color[i] = top + (bottom - top) * fy;
+        }
+        color
+    }
+
+    /// Texture coordinates of the center of every pixel, row by row.
+    fn uvs(size: [u32; 2]) -> impl Iterator<Item = [f32; 2]> {
+        let [width, height] = size;
+        (0..height).flat_map(move |y| {
+            (0..width).map(move |x| [(x as f32 + 0.5) / width as f32, (y as f32 + 0.5) / height as f32])
+        })
+    }
+
+    /// Pixels as stored in an `Rgba8Srgb` image.
+    pub fn to_srgb8(&self) -> Vec<u8> {
+        fn encode(c: f32) -> u8 {
+            let c = c.clamp(0., 1.);
+            let c = if c <= 0.0031308 {
+                c * 12.92
+            } else {
+                1.055 * c.powf(1. / 2.4) - 0.055
+            };
+            (c * 255.).round() as u8
+        }
+
+        self.pixels
+            .iter()
+            .flat_map(|p| {
+                vec![encode(p[0]), encode(p[1]), encode(p[2]), (p[3].clamp(0., 1.) * 255.).round() as u8]
+            })
+            .collect()
+    }
+
+    /// Pixels as stored in an `Rgba32Sfloat` image.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|p| p.iter())
+            .flat_map(|c| c.to_le_bytes().to_vec())
+            .collect()
+    }
+}
+
+/// Both images the graph produces for a frame.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame {
+    pub hdr: Framebuffer,
+    pub color: Framebuffer,
+}
+
+/// Renders the objects of `scene` as seen from `camera`, lit by `light`.
+///
+/// Only the first frame is rendered, which temporal anti-aliasing leaves as it is.
+pub fn render(
+    camera: &Camera,
+    light: &DirectionalLight,
+    scene: &Scene,
+    meshes: &[MeshData],
+    textures: &[TextureData],
+    settings: &RenderSettings,
+    size: [u32; 2],
+) -> Frame {
+    let hdr = render_meshes(camera, light, scene, meshes, textures, settings, size);
+    let mut color = match &settings.bloom {
+        Some(bloom) => post_effects(&self::bloom(&hdr, bloom), &settings.effects),
+        None => post_effects(&hdr, &settings.effects),
+    };
+    if settings.antialiasing == AntiAliasing::Fxaa {
+        color = fxaa(&color);
+    }
+    Frame { hdr, color }
+}
+
+/// Equivalent of the shadow pass, the mesh pass and the resolve after it, renders into a freshly
+/// cleared `hdr` image.
+pub fn render_meshes(
+    camera: &Camera,
+    light: &DirectionalLight,
+    scene: &Scene,
+    meshes: &[MeshData],
+    textures: &[TextureData],
+    settings: &RenderSettings,
+    size: [u32; 2],
+) -> Framebuffer {
+    let transforms = light.get_cascade_transforms(camera, settings.cascades);
+    let lighting = Lighting {
+        direction: light.direction.normalize(),
+        color: light.color,
+        shadow_maps: transforms
+            .iter()
+            .map(|transform| render_shadow_map(transform, scene, meshes))
+            .collect(),
+        transforms,
+        debug_cascades: settings.debug_cascades,
+    };
+
+    let mut target = Samples::new(size, settings.samples);
+    let transform = camera.get_transform();
+    let white = TextureData::white();
+    for instance in scene.instances() {
+        let texture = instance.texture.map_or(&white, |texture| &textures[texture]);
+        let mesh = &meshes[instance.mesh];
+        draw_mesh(&mut target, &transform, &instance.model, mesh, false, &|fragment| {
+            shade(fragment, texture, &instance.color, &lighting)
+        });
+    }
+
+    target.resolve()
+}
+
+/// Equivalent of the shadow pass for a single cascade, renders the depth of `scene` into
+/// a freshly cleared shadow map. The atlas keeps the cascades apart by scissoring.
+///
+/// Like on devices with depth clamping, occluders in front of the cascade aren't clipped.
+fn render_shadow_map(transform: &Matrix4<f32>, scene: &Scene, meshes: &[MeshData]) -> ShadowMap {
+    let mut target = Samples::depth_only([SHADOW_MAP_SIZE; 2]);
+    for instance in scene.instances() {
+        let mesh = &meshes[instance.mesh];
+        // there is no fragment shader, depth-only targets never shade
+        draw_mesh(&mut target, transform, &instance.model, mesh, true, &|_| [0.; 4]);
+    }
+    ShadowMap {
+        size: SHADOW_MAP_SIZE,
+        depth: target.depth,
+    }
+}
+
+/// Depth image of the shadow pass for a single cascade, rows from top to bottom.
+struct ShadowMap {
+    size: u32,
+    depth: Vec<f32>,
+}
+
+impl ShadowMap {
+    /// Equivalent of a lookup with the comparison sampler of the mesh pass. Blends whether
+    /// `reference` is at most the depth of each of the four texels around `uv`, outside of the map is lit.
+    fn compare(&self, uv: &Vector2<f32>, reference: f32) -> f32 {
+        let x = uv.x * self.size as f32 - 0.5;
+        let y = uv.y * self.size as f32 - 0.5;
+        let (fx, fy) = (x - x.floor(), y - y.floor());
+        let lit = |x: i64, y: i64| {
+            let inside = (0..self.size as i64).contains(&x) && (0..self.size as i64).contains(&y);
+            let depth = if inside { self.depth[(y * self.size as i64 + x) as usize] } else { 1. };
+            if reference <= depth { 1. } else { 0. }
+        };
+        let (x, y) = (x.floor() as i64, y.floor() as i64);
+        let top = lit(x, y) * (1. - fx) + lit(x + 1, y) * fx;
+        let bottom = lit(x, y + 1) * (1. - fx) + lit(x + 1, y + 1) * fx;
+        top * (1. - fy) + bottom * fy
+    }
+}
+
+/// Uniforms of `mesh.frag` besides the albedo.
+struct Lighting {
+    /// Unit length.
+    direction: Vector3<f32>,
+    color: Vector3<f32>,
+    /// Transform and shadow map of each cascade, the nearest first.
+    transforms: Vec<Matrix4<f32>>,
+    shadow_maps: Vec<ShadowMap>,
+    /// Whether `DEBUG_CASCADES` is defined.
+    debug_cascades: bool,
+}
+
+impl Lighting {
+    /// Equivalent of `shadow` in `lighting.glsl`, also returns the cascade if there is one.
+    fn shadow(&self, world: &Vector4<f32>, diffuse: f32) -> (f32, Option<usize>) {
+        const MIN_SHADOW_BIAS: f32 = 0.0005;
+        const MAX_SHADOW_BIAS: f32 = 0.005;
+
+        for (cascade, (transform, shadow_map)) in self.transforms.iter().zip(&self.shadow_maps).enumerate() {
+            let coord = transform * world;
+            let ndc = coord.xyz() / coord.w;
+            let uv = ndc.xy() * 0.5 + Vector2::new(0.5, 0.5);
+            let texel = 1. / shadow_map.size as f32;
+            let margin = 2. * texel;
+            if uv.min() < margin || uv.max() > 1. - margin || ndc.z > 1. {
+                continue;
+            }
+
+            let depth = ndc.z - (MAX_SHADOW_BIAS + (MIN_SHADOW_BIAS - MAX_SHADOW_BIAS) * diffuse);
+            let mut lit = 0.;
+            for y in -1..=1 {
+                for x in -1..=1 {
+                    lit += shadow_map.compare(&(uv + Vector2::new(x as f32, y as f32) * texel), depth);
+                }
+            }
+            return (lit / 9., Some(cascade));
+        }
+        (1., None)
+    }
+}
+
+/// Standard sample locations within a pixel for each sample count.
+fn sample_positions(samples: u8) -> &'static [[f32; 2]] {
+    match samples {
+        1 => &[[0.5, 0.5]],
+        2 => &[[0.75, 0.75], [0.25, 0.25]],
+        4 => &[[0.375, 0.125], [0.875, 0.375], [0.125, 0.625], [0.625, 0.875]],
+        8 => &[
+            [0.5625, 0.3125], [0.4375, 0.6875], [0.8125, 0.5625], [0.3125, 0.1875],
+            [0.1875, 0.8125], [0.0625, 0.4375], [0.6875, 0.9375], [0.9375, 0.0625],
+        ],
+        16 => &[
+            [0.5625, 0.5625], [0.4375, 0.3125], [0.3125, 0.625], [0.75, 0.4375],
+            [0.1875, 0.375], [0.625, 0.8125], [0.8125, 0.6875], [0.6875, 0.1875],
+            [0.375, 0.875], [0.5, 0.0625], [0.25, 0.125], [0.125, 0.75],
+            [0.0, 0.5], [0.9375, 0.25], [0.875, 0.9375], [0.0625, 0.0],
+        ],
+        _ => panic!("No standard sample locations for {} samples", samples),
+    }
+}
+
+/// Color and depth of every sample of the mesh pass, the samples of a pixel are next to each other.
+struct Samples {
+    size: [u32; 2],
+    positions: &'static [[f32; 2]],
+    /// `None` for depth-only targets, which skip shading altogether.
+    colors: Option<Vec<[f32; 4]>>,
+    depth: Vec<f32>,
+}
+
+impl Samples {
+    fn new(size: [u32; 2], samples: u8) -> Self {
+        let count = size[0] as usize * size[1] as usize * samples as usize;
+        Self {
+            size,
+            positions: sample_positions(samples),
+            colors: Some(vec![CLEAR_COLOR; count]),
+            depth: vec![1.; count],
+        }
+    }
+
+    /// Single sampled target without colors, like the shadow pass renders to.
+    fn depth_only(size: [u32; 2]) -> Self {
+        Self {
+            size,
+            positions: sample_positions(1),
+            colors: None,
+            depth: vec![1.; size[0] as usize * size[1] as usize],
+        }
+    }
+
+    /// Average of the samples of each pixel, like `resolve::Resolve` for float images.
+    /// Panics for depth-only targets.
+    fn resolve(&self) -> Framebuffer {
+        let pixels = self
+            .colors
+            .as_ref()
+            .expect("Depth-only targets can't be resolved.")
+            .chunks_exact(self.positions.len())
+            .map(|samples| {
+                let mut color = [0.; 4];
+                for sample in samples {
+                    for i in 0..4 {
+                        color[i] += sample[i] / samples.len() as f32;
+                    }
+                }
+                color
+            })
+            .collect();
+        Framebuffer { size: self.size, pixels }
+    }
+}
+
+/// Output of `mesh.vert`, `normal` is `frag_norm`, `uv` is `frag_uv` and `world` is `frag_world`.
+#[derive(Clone, Copy, Debug)]
+struct ClipVertex {
+    position: Vector4<f32>,
+    normal: Vector3<f32>,
+    uv: Vector2<f32>,
+    world: Vector4<f32>,
+}
+
+impl ClipVertex {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        Self {
+            position: self.position + (other.position - self.position) * t,
+            normal: self.normal + (other.normal - self.normal) * t,
+            uv: self.uv + (other.uv - self.uv) * t,
+            world: self.world + (other.world - self.world) * t,
+        }
+    }
+}
+
+/// Vertex in framebuffer coordinates, attributes are divided by `w`
+/// so they can be interpolated perspective correctly.
+#[derive(Clone, Copy, Debug)]
+struct ScreenVertex {
+    position: Vector3<f32>,
+    inv_w: f32,
+    normal: Vector3<f32>,
+    uv: Vector2<f32>,
+    world: Vector4<f32>,
+}
+
+/// Interpolated inputs of `mesh.frag`.
+struct Fragment {
+    normal: Vector3<f32>,
+    uv: Vector2<f32>,
+    world: Vector4<f32>,
+}
+
+/// With `depth_clamp` nothing is clipped, which only works for orthographic `transform`s.
+fn draw_mesh(
+    target: &mut Samples,
+    transform: &Matrix4<f32>,
+    model: &Matrix4<f32>,
+    mesh: &MeshData,
+    depth_clamp: bool,
+    shade: &dyn Fn(&Fragment) -> [f32; 4],
+) {
+    let size = target.size;
+    let transform = transform * model;
+    let normal_matrix = model
+        .fixed_slice::<nalgebra::U3, nalgebra::U3>(0, 0)
+        .try_inverse()
+        .unwrap_or_else(Matrix3::identity)
+        .transpose();
+
+    for triangle in mesh.indices.chunks_exact(3) {
+        let vertices: Vec<_> = triangle
+            .iter()
+            .map(|&i| {
+                let [x, y, z] = mesh.positions[i as usize];
+                let p = Vector4::new(x, y, z, 1.);
+                ClipVertex {
+                    position: transform * p,
+                    normal: normal_matrix * Vector3::from(mesh.normals[i as usize]),
+                    uv: Vector2::from(mesh.tex_coords[i as usize]),
+                    world: model * p,
+                }
+            })
+            .collect();
+
+        let clipped = if depth_clamp { vertices } else { clip_polygon(vertices) };
+        for i in 1..clipped.len().saturating_sub(1) {
+            let screen = [
+                to_screen(&clipped[0], size),
+                to_screen(&clipped[i], size),
+                to_screen(&clipped[i + 1], size),
+            ];
+            rasterize(target, screen, shade);
+        }
+    }
+}
+
+/// Tints of `mesh.frag` with `DEBUG_CASCADES` defined.
+const CASCADE_COLORS: [[f32; 3]; 4] = [[1., 0.3, 0.3], [0.3, 1., 0.3], [0.3, 0.3, 1.], [1., 1., 0.3]];
+
+/// Equivalent of `mesh.frag`.
+fn shade(fragment: &Fragment, texture: &TextureData, tint: &[f32; 4], lighting: &Lighting) -> [f32; 4] {
+    let diffuse = (-fragment.normal.normalize()).dot(&lighting.direction).max(0.);
+    let (lit, cascade) = lighting.shadow(&fragment.world, diffuse);
+    let mut color = lighting.color * diffuse * lit;
+    if let (true, Some(cascade)) = (lighting.debug_cascades, cascade) {
+        color.component_mul_assign(&Vector3::from(CASCADE_COLORS[cascade % 4]));
+    }
+    let albedo = texture.sample([fragment.uv.x, fragment.uv.y]);
+    let albedo = [albedo[0] * tint[0], albedo[1] * tint[1], albedo[2] * tint[2]];
+    [color.x * albedo[0], color.y * albedo[1], color.z * albedo[2], 1.]
+}
+
+/// Equivalent of the bloom passes, returns `hdr` with the bloom added.
+pub fn bloom(hdr: &Framebuffer, bloom: &Bloom) -> Framebuffer {
+    let sizes = bloom.level_sizes(hdr.size);
+    let mut levels = vec![downsample(hdr, sizes[0], Some(bloom.threshold))];
+    for &size in &sizes[1..] {
+        let level = downsample(levels.last().unwrap(), size, None);
+        levels.push(level);
+    }
+    for i in (1..levels.len()).rev() {
+        let (upper, lower) = levels.split_at_mut(i);
+        add_upsampled(&mut upper[i - 1], &lower[0], 1.);
+    }
+
+    let mut bloomed = hdr.clone();
+    add_upsampled(&mut bloomed, &levels[0], bloom.intensity);
+    bloomed
+}
+
+/// Equivalent of `bloom_downsample.frag`, `threshold` is set with `PREFILTER` defined.
+fn downsample(source: &Framebuffer, size: [u32; 2], threshold: Option<f32>) -> Framebuffer {
+    let texel = [1. / source.size[0] as f32, 1. / source.size[1] as f32];
+    let pixels = Framebuffer::uvs(size)
+        .map(|uv| {
+            let mut c = [0.; 3];
+            for &(dx, dy) in &[(-1., -1.), (1., -1.), (-1., 1.), (1., 1.)] {
+                let tap = source.sample([uv[0] + texel[0] * dx, uv[1] + texel[1] * dy]);
+                for i in 0..3 {
+                    c[i] += tap[i] * 0.25;
+                }
+            }
+
+            if let Some(threshold) = threshold {
+                let c0 = c.map(|c| c.max(0.));
+                let brightness = c0[0].max(c0[1]).max(c0[2]);
+                let knee = threshold * 0.5;
+                let soft = (brightness - threshold + knee).clamp(0., 2. * knee);
+                let soft = soft * soft / (4. * knee + 0.00001);
+                let scale = soft.max(brightness - threshold) / brightness.max(0.00001);
+                c = c0.map(|c| c * scale);
+            }
+            [c[0], c[1], c[2], 1.]
+        })
+        .collect();
+    Framebuffer { size, pixels }
+}
+
+/// Equivalent of `bloom_upsample.frag` blended onto `target`, `scale` is the intensity
+/// with `COMPOSITE` defined.
+fn add_upsampled(target: &mut Framebuffer, source: &Framebuffer, scale: f32) {
+    let texel = [1. / source.size[0] as f32, 1. / source.size[1] as f32];
+    for (pixel, uv) in target.pixels.iter_mut().zip(Framebuffer::uvs(target.size)) {
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                let weight = ((2 - dx * dx) * (2 - dy * dy)) as f32 / 16.;
+                let tap = source.sample([uv[0] + texel[0] * dx as f32, uv[1] + texel[1] * dy as f32]);
+                for i in 0..3 {
+                    pixel[i] += tap[i] * weight * scale;
+                }
+            }
+        }
+    }
+}
+
+/// Equivalent of the post effect chain, only knows the effects that ship in `assets`
+/// and ignores any of their defines but `OPERATOR` and `AUTO_EXPOSURE` of `tonemap.frag`.
+pub fn post_effects(hdr: &Framebuffer, effects: &[PostEffect]) -> Framebuffer {
+    let mut image = hdr.clone();
+    for effect in effects {
+        image = match effect.shader.as_str() {
+            "posteffect.frag" => {
+                post_effect(&image, float(effect, "frequency"), float(effect, "amplitude"))
+            }
+            "passthrough.frag" => image,
+            "tonemap.frag" => {
+                let operator = effect
+                    .defines
+                    .iter()
+                    .find(|(name, _)| name == "OPERATOR")
+                    .map_or("ACES", |(_, value)| value.as_str());
+                let mut exposure = float(effect, "exposure");
+                // a single frame has nothing to adapt from
+                if effect.defines.iter().any(|(name, _)| name == "AUTO_EXPOSURE") {
+                    exposure += (MIDDLE_GRAY / average_luminance(&image)).log2();
+                }
+                tonemap(&image, operator, exposure)
+            }
+            shader => panic!("No reference implementation of {}", shader),
+        };
+    }
+    image
+}
+
+/// Parameter the way the pass pushes it, zero unless it is set to a float.
+fn float(effect: &PostEffect, name: &str) -> f32 {
+    match effect.parameter(name) {
+        Some(ParameterValue::Float(value)) => value,
+        _ => 0.,
+    }
+}
+
+const MIDDLE_GRAY: f32 = 0.18;
+const MIN_LOG_LUMINANCE: f32 = -10.;
+const LOG_LUMINANCE_RANGE: f32 = 22.;
+
+/// Equivalent of `histogram.comp` and `average_luminance.comp` on the first frame,
+/// including the precision lost to the bins of the histogram.
+pub fn average_luminance(hdr: &Framebuffer) -> f32 {
+    let (mut weights, mut count) = (0., 0.);
+    for pixel in &hdr.pixels {
+        let l = 0.2126 * pixel[0] + 0.7152 * pixel[1] + 0.0722 * pixel[2];
+        if l < MIN_LOG_LUMINANCE.exp2() {
+            continue;
+        }
+        let t = ((l.log2() - MIN_LOG_LUMINANCE) / LOG_LUMINANCE_RANGE).clamp(0., 1.);
+        let bin = (t * 254. + 1.) as u32;
+        weights += bin as f64;
+        count += 1.;
+    }
+
+    if count == 0. {
+        return MIN_LOG_LUMINANCE.exp2();
+    }
+    let t = (weights / count - 1.) as f32 / 254.;
+    (t * LOG_LUMINANCE_RANGE + MIN_LOG_LUMINANCE).exp2()
+}
+
+/// Equivalent of `tonemap.frag`, `operator` is the name or value of one of its operators.
+pub fn tonemap(hdr: &Framebuffer, operator: &str, exposure: f32) -> Framebuffer {
+    fn aces(x: f32) -> f32 {
+        ((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)).clamp(0., 1.)
+    }
+    fn uncharted2_curve(x: f32) -> f32 {
+        let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+        ((x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f)) - e / f
+    }
+    fn reinhard(x: f32) -> f32 {
+        x / (1. + x)
+    }
+    fn uncharted2(x: f32) -> f32 {
+        uncharted2_curve(2. * x) / uncharted2_curve(11.2)
+    }
+
+    let curve: fn(f32) -> f32 = match operator {
+        "REINHARD" | "0" => reinhard,
+        "ACES" | "1" => aces,
+        "UNCHARTED2" | "2" => uncharted2,
+        _ => panic!("Unknown tonemapping operator {}", operator),
+    };
+    let scale = exposure.exp2();
+
+    let mut target = hdr.clone();
+    for pixel in &mut target.pixels {
+        for c in &mut pixel[..3] {
+            *c = curve(c.max(0.) * scale);
+        }
+    }
+    target
+}
+
+/// Equivalent of `posteffect.frag`, samples `hdr` with the sine warp and inverts it.
+pub fn post_effect(hdr: &Framebuffer, frequency: f32, amplitude: f32) -> Framebuffer {
+    let [width, height] = hdr.size;
+    let mut target = Framebuffer::new(hdr.size, CLEAR_COLOR);
+
+    for y in 0..height {
+        for x in 0..width {
+            let frag_x = x as f32 + 0.5;
+            let frag_y = y as f32 + 0.5;
+
+            let u = frag_x / width as f32;
+            let v = frag_y / height as f32 + (frag_x * frequency).sin() * amplitude;
+
+            // nearest filtering with clamped addressing
+            let tx = ((u * width as f32).floor().max(0.) as u32).min(width - 1);
+            let ty = ((v * height as f32).floor().max(0.) as u32).min(height - 1);
+            let pixel = hdr.get(tx, ty);
+
+            target.pixels[(y * width + x) as usize] =
+                [1. - pixel[0], 1. - pixel[1], 1. - pixel[2], pixel[3]];
+        }
+    }
+
+    target
+}
+
+const FXAA_EDGE_THRESHOLD_MIN: f32 = 0.0312;
+const FXAA_EDGE_THRESHOLD_MAX: f32 = 0.125;
+const FXAA_SUBPIXEL_QUALITY: f32 = 0.75;
+const FXAA_SEARCH_STEP: [f32; 12] = [1., 1., 1., 1., 1., 1.5, 2., 2., 2., 2., 4., 8.];
+
+/// Equivalent of `fxaa.frag`.
+pub fn fxaa(color: &Framebuffer) -> Framebuffer {
+    let texel = [1. / color.size[0] as f32, 1. / color.size[1] as f32];
+    let luma = |uv: [f32; 2]| {
+        let c = color.sample(uv).map(|c| c.clamp(0., 1.));
+        (0.2126 * c[0] + 0.7152 * c[1] + 0.0722 * c[2]).sqrt()
+    };
+    let luma_offset = |uv: [f32; 2], x: f32, y: f32| luma([uv[0] + x * texel[0], uv[1] + y * texel[1]]);
+
+    let pixels = Framebuffer::uvs(color.size)
+        .map(|uv| {
+            let center = color.sample(uv);
+            let luma_center = luma(uv);
+            let luma_up = luma_offset(uv, 0., -1.);
+            let luma_down = luma_offset(uv, 0., 1.);
+            let luma_left = luma_offset(uv, -1., 0.);
+            let luma_right = luma_offset(uv, 1., 0.);
+
+            let luma_min = luma_center.min(luma_up).min(luma_down).min(luma_left).min(luma_right);
+            let luma_max = luma_center.max(luma_up).max(luma_down).max(luma_left).max(luma_right);
+            let range = luma_max - luma_min;
+            if range < FXAA_EDGE_THRESHOLD_MIN.max(luma_max * FXAA_EDGE_THRESHOLD_MAX) {
+                return center;
+            }
+
+            let luma_up_left = luma_offset(uv, -1., -1.);
+            let luma_up_right = luma_offset(uv, 1., -1.);
+            let luma_down_left = luma_offset(uv, -1., 1.);
+            let luma_down_right = luma_offset(uv, 1., 1.);
+
+            let luma_up_down = luma_up + luma_down;
+            let luma_left_right = luma_left + luma_right;
+            let luma_left_corners = luma_up_left + luma_down_left;
+            let luma_right_corners = luma_up_right + luma_down_right;
+            let luma_up_corners = luma_up_left + luma_up_right;
+            let luma_down_corners = luma_down_left + luma_down_right;
+
+            let edge_horizontal = (luma_left_corners - 2. * luma_left).abs()
+                + 2. * (luma_up_down - 2. * luma_center).abs()
+                + (luma_right_corners - 2. * luma_right).abs();
+            let edge_vertical = (luma_up_corners - 2. * luma_up).abs()
+                + 2. * (luma_left_right - 2. * luma_center).abs()
+                + (luma_down_corners - 2. * luma_down).abs();
+            let horizontal = edge_horizontal >= edge_vertical;
+            // along the edge and across it
+            let (along, across) = if horizontal { (0, 1) } else { (1, 0) };
+
+            let (luma_negative, luma_positive) =
+                if horizontal { (luma_up, luma_down) } else { (luma_left, luma_right) };
+            let gradient_negative = luma_negative - luma_center;
+            let gradient_positive = luma_positive - luma_center;
+            let negative = gradient_negative.abs() >= gradient_positive.abs();
+            let gradient_scaled = 0.25 * gradient_negative.abs().max(gradient_positive.abs());
+
+            let (step_length, luma_local_average) = if negative {
+                (-texel[across], 0.5 * (luma_negative + luma_center))
+            } else {
+                (texel[across], 0.5 * (luma_positive + luma_center))
+            };
+
+            let mut edge_uv = uv;
+            edge_uv[across] += 0.5 * step_length;
+
+            let (mut uv1, mut uv2) = (edge_uv, edge_uv);
+            uv1[along] -= texel[along];
+            uv2[along] += texel[along];
+            let mut luma_end1 = luma(uv1) - luma_local_average;
+            let mut luma_end2 = luma(uv2) - luma_local_average;
+            let mut reached1 = luma_end1.abs() >= gradient_scaled;
+            let mut reached2 = luma_end2.abs() >= gradient_scaled;
+            for step in &FXAA_SEARCH_STEP[1..] {
+                if reached1 && reached2 {
+                    break;
+                }
+                if !reached1 {
+                    uv1[along] -= texel[along] * step;
+                    luma_end1 = luma(uv1) - luma_local_average;
+                    reached1 = luma_end1.abs() >= gradient_scaled;
+                }
+                if !reached2 {
+                    uv2[along] += texel[along] * step;
+                    luma_end2 = luma(uv2) - luma_local_average;
+                    reached2 = luma_end2.abs() >= gradient_scaled;
+                }
+            }
+
+            let distance1 = uv[along] - uv1[along];
+            let distance2 = uv2[along] - uv[along];
+            let closer1 = distance1 < distance2;
+            let pixel_offset = 0.5 - distance1.min(distance2) / (distance1 + distance2);
+
+            let center_smaller = luma_center < luma_local_average;
+            let correct_variation = ((if closer1 { luma_end1 } else { luma_end2 }) < 0.) != center_smaller;
+            let mut final_offset = if correct_variation { pixel_offset } else { 0. };
+
+            let luma_average =
+                (2. * (luma_up_down + luma_left_right) + luma_left_corners + luma_right_corners) / 12.;
+            let subpixel = ((luma_average - luma_center).abs() / range).clamp(0., 1.);
+            let subpixel = (3. - 2. * subpixel) * subpixel * subpixel;
+            final_offset = final_offset.max(subpixel * subpixel * FXAA_SUBPIXEL_QUALITY);
+
+            let mut final_uv = uv;
+            final_uv[across] += final_offset * step_length;
+            color.sample(final_uv)
+        })
+        .collect();
+    Framebuffer { size: color.size, pixels }
+}
+
+/// Clips a polygon against the `0 <= z <= w` depth range.
+fn clip_polygon(mut polygon: Vec<ClipVertex>) -> Vec<ClipVertex> {
+    let planes: [fn(&Vector4<f32>) -> f32; 2] = [|v| v.z, |v| v.w - v.z];
+
+    for plane in planes.iter() {
+        let mut output = Vec::with_capacity(polygon.len() + 1);
+        for i in 0..polygon.len() {
+            let current = polygon[i];
+            let next = polygon[(i + 1) % polygon.len()];
+            let (dc, dn) = (plane(&current.position), plane(&next.position));
+
+            if dc >= 0. {
+                output.push(current);
+            }
+            if (dc >= 0.) != (dn >= 0.) {
+                output.push(current.lerp(&next, dc / (dc - dn)));
+            }
+        }
+        polygon = output;
+    }
+
+    polygon
+}
+
+fn to_screen(vertex: &ClipVertex, size: [u32; 2]) -> ScreenVertex {
+    let clip = vertex.position;
+    ScreenVertex {
+        position: Vector3::new(
+            (clip.x / clip.w + 1.) * 0.5 * size[0] as f32,
+            (clip.y / clip.w + 1.) * 0.5 * size[1] as f32,
+            clip.z / clip.w,
+        ),
+        inv_w: 1. / clip.w,
+        normal: vertex.normal / clip.w,
+        uv: vertex.uv / clip.w,
+        world: vertex.world / clip.w,
+    }
+}
+
+fn edge(a: &Vector3<f32>, b: &Vector3<f32>, x: f32, y: f32) -> f32 {
+    (b.x - a.x) * (y - a.y) - (b.y - a.y) * (x - a.x)
+}
+
+/// Top-left fill rule for a triangle with positive area.
+fn is_top_left(a: &Vector3<f32>, b: &Vector3<f32>) -> bool {
+    (a.y == b.y && b.x > a.x) || b.y < a.y
+}
+
+fn rasterize(target: &mut Samples, mut tri: [ScreenVertex; 3], shade: &dyn Fn(&Fragment) -> [f32; 4]) {
+    let mut area = edge(&tri[0].position, &tri[1].position, tri[2].position.x, tri[2].position.y);
+    if area == 0. {
+        return;
+    }
+    // there is no culling, so both windings get drawn
+    if area < 0. {
+        tri.swap(1, 2);
+        area = -area;
+    }
+
+    let [width, height] = target.size;
+    let xs = tri.iter().map(|v| v.position.x);
+    let ys = tri.iter().map(|v| v.position.y);
+    let min_x = xs.clone().fold(f32::INFINITY, f32::min).floor().max(0.) as u32;
+    let min_y = ys.clone().fold(f32::INFINITY, f32::min).floor().max(0.) as u32;
+    let max_x = (xs.fold(f32::NEG_INFINITY, f32::max).ceil().max(0.) as u32).min(width);
+    let max_y = (ys.fold(f32::NEG_INFINITY, f32::max).ceil().max(0.) as u32).min(height);
+
+    let edges = [(1, 2), (2, 0), (0, 1)];
+    let weights = |px: f32, py: f32| {
+        let mut weights = [0f32; 3];
+        for (i, &(a, b)) in edges.iter().enumerate() {
+            weights[i] = edge(&tri[a].position, &tri[b].position, px, py) / area;
+        }
+        weights
+    };
+    let samples = target.positions.len();
+
+    for y in min_y..max_y {
+        for x in min_x..max_x {
+            let pixel = (y * width + x) as usize;
+
+            // coverage and depth are per sample
+            let mut covered = 0u32;
+            for (s, position) in target.positions.iter().enumerate() {
+                let (px, py) = (x as f32 + position[0], y as f32 + position[1]);
+                let inside = edges.iter().all(|&(a, b)| {
+                    let (a, b) = (&tri[a].position, &tri[b].position);
+                    let w = edge(a, b, px, py);
+                    w > 0. || (w == 0. && is_top_left(a, b))
+                });
+                if !inside {
+                    continue;
+                }
+
+                // depth is linear in screen space, attributes are not
+                let weights = weights(px, py);
+                // clipped triangles are within range already, unclipped ones are clamped to it
+                let z = (0..3).map(|i| weights[i] * tri[i].position.z).sum::<f32>().clamp(0., 1.);
+                let index = pixel * samples + s;
+                if z < target.depth[index] {
+                    target.depth[index] = z;
+                    covered |= 1 << s;
+                }
+            }
+            let colors = match &mut target.colors {
+                Some(colors) if covered != 0 => colors,
+                _ => continue,
+            };
+
+            // the fragment is shaded once at the center of the pixel, even outside the triangle
+            let weights = weights(x as f32 + 0.5, y as f32 + 0.5);
+            let inv_w: f32 = (0..3).map(|i| weights[i] * tri[i].inv_w).sum();
+            let normal: Vector3<f32> = (0..3).map(|i| tri[i].normal * weights[i]).sum();
+            let uv: Vector2<f32> = (0..3).map(|i| tri[i].uv * weights[i]).sum();
+            let world: Vector4<f32> = (0..3).map(|i| tri[i].world * weights[i]).sum();
+            let color = shade(&Fragment {
+                normal: normal / inv_w,
+                uv: uv / inv_w,
+                world: world / inv_w,
+            });
+            for s in 0..samples {
+                if covered & 1 << s != 0 {
+                    colors[pixel * samples + s] = color;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::{geometry, scene};
+
+    /// Regenerates the golden images instead of comparing against them when set.
+    const UPDATE_GOLDEN: &str = "UPDATE_GOLDEN";
+
+    /// Compares `frame` with `tests/golden/<name>.png`, allowing each channel to be one step off.
+    fn assert_golden(name: &str, frame: &Framebuffer) {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(name).with_extension("png");
+        let pixels = frame.to_srgb8();
+        if std::env::var_os(UPDATE_GOLDEN).is_some() {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            crate::headless::write_png(&path, frame.size, &pixels).unwrap();
+            return;
+        }
+
+        let golden = image::open(&path)
+            .unwrap_or_else(|e| panic!("Couldn't open {}, set {} to create it: {}", path.display(), UPDATE_GOLDEN, e))
+            .to_rgba();
+        assert_eq!(golden.dimensions(), (frame.size[0], frame.size[1]));
+        let differing = golden
+            .into_raw()
+            .iter()
+            .zip(&pixels)
+            .filter(|(a, b)| (**a as i16 - **b as i16).abs() > 1)
+            .count();
+        assert_eq!(differing, 0, "{} channels differ from {}", differing, path.display());
+    }
+
+    #[test]
+    fn spheres_match_golden_image() {
+        let settings = RenderSettings {
+            effects: vec![PostEffect::new("posteffect.frag")
+                .with_parameter("frequency", 0.05)
+                .with_parameter("amplitude", 0.05)],
+            // a single shadow map keeps the test quick
+            cascades: 1,
+            ..Default::default()
+        };
+        let frame = render(
+            &Camera::new(1.),
+            &DirectionalLight::default(),
+            &scene::spheres(3),
+            &[geometry::icosphere()],
+            &[],
+            &settings,
+            [64, 64],
+        );
+        assert_golden("spheres", &frame.color);
+    }
+
+    #[test]
+    fn occluders_beyond_the_cascade_still_cast_shadows() {
+        let camera = Camera::new(1.);
+        let light = DirectionalLight::default();
+        let direction = light.direction.normalize();
+        let (center, radius) = camera.get_bounding_sphere(0. ..1.);
+
+        // a quad facing the light, well outside of the sphere on the side the light comes from
+        let across = direction.cross(&Vector3::z()).normalize() * radius * 0.25;
+        let up = direction.cross(&across);
+        let corners = [-across - up, across - up, across + up, -across + up];
+        let quad = MeshData {
+            name: "occluder".to_string(),
+            indices: vec![0, 1, 2, 0, 2, 3],
+            positions: corners.iter().map(|c| [c.x, c.y, c.z]).collect(),
+            normals: vec![[-direction.x, -direction.y, -direction.z]; 4],
+            tex_coords: vec![[0., 0.]; 4],
+            material: None,
+        };
+        let mut scene = Scene::default();
+        scene.add(crate::scene::Object {
+            name: "occluder".to_string(),
+            mesh: Some(0),
+            texture: None,
+            color: [1.; 4],
+            transform: Matrix4::new_translation(&(center.coords - direction * (radius + 5.))),
+            parent: None,
+        });
+
+        let transforms = light.get_cascade_transforms(&camera, 1);
+        let lighting = Lighting {
+            direction,
+            color: light.color,
+            shadow_maps: vec![render_shadow_map(&transforms[0], &scene, &[quad])],
+            transforms,
+            debug_cascades: false,
+        };
+        let lit = |point: Vector3<f32>| lighting.shadow(&point.push(1.), 1.).0;
+        assert_eq!(lit(center.coords), 0.);
+        assert_eq!(lit(center.coords + across * 2.), 1.);
+    }
+
+    fn clip_vertex(x: f32, y: f32, z: f32, w: f32) -> ClipVertex {
+        ClipVertex {
+            position: Vector4::new(x, y, z, w),
+            normal: Vector3::zeros(),
+            uv: Vector2::zeros(),
+            world: Vector4::zeros(),
+        }
+    }
+
+    #[test]
+    fn clipping_keeps_depth_within_range() {
+        let inside = vec![clip_vertex(0., 0., 0.5, 1.), clip_vertex(1., 0., 0.5, 1.), clip_vertex(0., 1., 0.5, 1.)];
+        assert_eq!(clip_polygon(inside).len(), 3);
+
+        let behind = vec![clip_vertex(0., 0., -1., 1.), clip_vertex(1., 0., -1., 1.), clip_vertex(0., 1., -2., 1.)];
+        assert!(clip_polygon(behind).is_empty());
+
+        // one vertex in front of the near plane and one beyond the far plane cut off a corner each
+        let crossing = vec![clip_vertex(0., 0., -0.5, 1.), clip_vertex(1., 0., 0.5, 1.), clip_vertex(0., 1., 1.5, 1.)];
+        let clipped = clip_polygon(crossing);
+        assert_eq!(clipped.len(), 5);
+        for vertex in &clipped {
+            let position = vertex.position;
+            assert!(position.z >= -1e-6 && position.z <= position.w + 1e-6, "{:?} is clipped", position);
+        }
+    }
+
+    fn screen_vertex(x: f32, y: f32) -> ScreenVertex {
+        ScreenVertex {
+            position: Vector3::new(x, y, 0.5),
+            inv_w: 1.,
+            normal: Vector3::zeros(),
+            uv: Vector2::zeros(),
+            world: Vector4::zeros(),
+        }
+    }
+
+    /// Samples of a framebuffer of `size` that `triangle` covers.
+    fn coverage(size: u32, triangle: [ScreenVertex; 3]) -> Vec<bool> {
+        let mut target = Samples::depth_only([size; 2]);
+        rasterize(&mut target, triangle, &|_| [0.; 4]);
+        target.depth.iter().map(|&depth| depth < 1.).collect()
+    }
+
+    #[test]
+    fn fill_rule_covers_shared_edges_once() {
+        // pixel centers on the diagonal lie exactly on the shared edge
+        let size = 8;
+        let [a, b, c, d] = [(0., 0.), (8., 0.), (8., 8.), (0., 8.)].map(|(x, y)| screen_vertex(x, y));
+        let upper = coverage(size, [a, b, c]);
+        let lower = coverage(size, [a, c, d]);
+        for (i, (upper, lower)) in upper.iter().zip(&lower).enumerate() {
+            assert!(upper ^ lower, "pixel {} is covered {} times", i, *upper as u8 + *lower as u8);
+        }
+
+        // the order of the vertices doesn't matter
+        assert_eq!(coverage(size, [a, c, b]), upper);
+    }
+
+    #[test]
+    fn srgb8_encodes_and_clamps() {
+        let framebuffer = Framebuffer {
+            size: [2, 2],
+            pixels: vec![[0., 1., 0.5, 0.5], [0.002, 2., -1., 1.], [0.; 4], [1.; 4]],
+        };
+        assert_eq!(
+            framebuffer.to_srgb8(),
+            vec![0, 255, 188, 128, 7, 255, 0, 255, 0, 0, 0, 0, 255, 255, 255, 255],
+        );
+    }
+}
@@ -0,0 +1,155 @@
+use nalgebra::{Matrix4, Vector3};
+
+/// Index of an object in a `Scene`.
+pub type ObjectId = usize;
+
+#[derive(Clone, Debug)]
+pub struct Object {
+    pub name: String,
+    /// Index of the mesh drawn for this object, objects without one only group their children.
+    pub mesh: Option<usize>,
+    /// Index of the albedo texture of the mesh, white when there is none.
+    pub texture: Option<usize>,
+    /// Linear color the albedo is multiplied by.
+    pub color: [f32; 4],
+    /// Transform relative to the parent object.
+    pub transform: Matrix4<f32>,
+    pub parent: Option<ObjectId>,
+}
+
+/// Object with a mesh and its transform relative to the world.
+#[derive(Clone, Debug)]
+pub struct Instance {
+    pub mesh: usize,
+    pub texture: Option<usize>,
+    pub color: [f32; 4],
+    pub model: Matrix4<f32>,
+}
+
+/// Hierarchy of objects referring to meshes by index.
+///
+/// Parents are always added before their children, so world transforms can be
+/// computed in a single pass over `objects`.
+#[derive(Clone, Debug, Default)]
+pub struct Scene {
+    pub objects: Vec<Object>,
+}
+
+impl Scene {
+    pub fn add(&mut self, object: Object) -> ObjectId {
+        if let Some(parent) = object.parent {
+            assert!(parent < self.objects.len(), "Parent must be added before its children.");
+        }
+        self.objects.push(object);
+        self.objects.len() - 1
+    }
+
+    /// Adds an object with an identity transform for every mesh, without any hierarchy.
+    pub fn from_meshes(names: impl IntoIterator<Item = String>) -> Self {
+        let mut scene = Scene::default();
+        for (mesh, name) in names.into_iter().enumerate() {
+            scene.add(Object {
+                name,
+                mesh: Some(mesh),
+                texture: None,
+                color: [1.; 4],
+                transform: Matrix4::identity(),
+                parent: None,
+            });
+        }
+        scene
+    }
+
+    /// Transform of every object relative to the world.
+    pub fn world_transforms(&self) -> Vec<Matrix4<f32>> {
+        let mut transforms: Vec<Matrix4<f32>> = Vec::with_capacity(self.objects.len());
+        for object in &self.objects {
+            let transform = match object.parent {
+                Some(parent) => transforms[parent] * object.transform,
+                None => object.transform,
+            };
+            transforms.push(transform);
+        }
+        transforms
+    }
+
+    /// Every object that is drawn.
+    pub fn instances(&self) -> Vec<Instance> {
+        self.objects
+            .iter()
+            .zip(self.world_transforms())
+            .filter_map(|(object, model)| {
+                object.mesh.map(|mesh| Instance {
+                    mesh,
+                    texture: object.texture,
+                    color: object.color,
+                    model,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Row of spheres in front of the default camera, all referring to mesh `0`.
+pub fn spheres(count: usize) -> Scene {
+    let mut scene = Scene::default();
+    let row = scene.add(Object {
+        name: "spheres".to_string(),
+        mesh: None,
+        texture: None,
+        color: [1.; 4],
+        transform: Matrix4::new_translation(&Vector3::new(0., -6., 0.)),
+        parent: None,
+    });
+
+    let spacing = 2.2;
+    let offset = (count as f32 - 1.) * spacing * 0.5;
+    for i in 0..count {
+        scene.add(Object {
+            name: format!("sphere {}", i),
+            mesh: Some(0),
+            texture: None,
+            color: [1.; 4],
+            transform: Matrix4::new_translation(&Vector3::new(i as f32 * spacing - offset, 0., 0.)),
+            parent: Some(row),
+        });
+    }
+    scene
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Point3;
+
+    use super::*;
+
+    fn object(mesh: Option<usize>, transform: Matrix4<f32>, parent: Option<ObjectId>) -> Object {
+        Object {
+            name: String::new(),
+            mesh,
+            texture: None,
+            color: [1.; 4],
+            transform,
+            parent,
+        }
+    }
+
+    #[test]
+    fn children_are_placed_relative_to_their_parents() {
+        let mut scene = Scene::default();
+        let root = scene.add(object(None, Matrix4::new_translation(&Vector3::new(1., 0., 0.)), None));
+        let turn = Matrix4::from_axis_angle(&Vector3::z_axis(), std::f32::consts::FRAC_PI_2);
+        let child = scene.add(object(Some(0), turn, Some(root)));
+        scene.add(object(Some(1), Matrix4::new_translation(&Vector3::new(0., 2., 0.)), Some(child)));
+
+        // only objects with a mesh are drawn
+        let instances = scene.instances();
+        assert_eq!(instances.iter().map(|instance| instance.mesh).collect::<Vec<_>>(), [0, 1]);
+
+        // the grandchild is moved along the turned `y` axis, then along the root's `x` axis
+        let origin = instances[1].model.transform_point(&Point3::origin());
+        assert!((origin - Point3::new(-1., 0., 0.)).norm() < 1e-6, "{} is misplaced", origin);
+        let origin = instances[0].model.transform_point(&Point3::origin());
+        assert!((origin - Point3::new(1., 0., 0.)).norm() < 1e-6, "{} is misplaced", origin);
+    }
+}
@@ -0,0 +1,659 @@
+//! GLSL shaders of the `assets` directory.
+//!
+//! Shaders are compiled the first time a pipeline asks for them and kept until `reload`
+//! replaces them, graphs built afterwards pick up the new code.
+//!
+//! `#include "..."` is resolved relative to the assets directory and pipelines can pass
+//! defines to build permutations of a shader, every permutation is compiled separately.
+//!
+//! Compiled SPIR-V is cached in `target/shader-cache`, an entry is only used while the
+//! shader and all of its includes still hash the same.
+//!
+//! With the `embed-shaders` feature the sources are compiled into the binary, nothing is
+//! read from the assets directory and the cache moves to the temporary directory.
+
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use rendy::{hal::pso::ShaderStageFlags, shader::SpirvShader};
+use shaderc::ShaderKind;
+
+use std::{
+    cell::RefCell,
+    collections::{hash_map::DefaultHasher, HashMap},
+    convert::TryInto,
+    fmt,
+    fs,
+    hash::{Hash, Hasher},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{channel, Receiver},
+        Mutex,
+    },
+};
+
+/// Macros defined before compiling a shader, as `(name, value)` pairs.
+pub type Defines<'a> = &'a [(&'a str, &'a str)];
+
+/// A shader together with every file it was compiled from and the hash of its contents.
+struct Compiled {
+    shader: SpirvShader,
+    files: HashMap<String, u64>,
+}
+
+/// Bump whenever the compile options change so stale cache entries are left alone.
+const CACHE_VERSION: u32 = 1;
+
+/// Name and defines of a shader permutation.
+type Key = (String, Vec<(String, String)>);
+
+lazy_static::lazy_static! {
+    static ref COMPILED: Mutex<HashMap<Key, Compiled>> = Mutex::new(HashMap::new());
+}
+
+/// Whether the sources are compiled into the binary, in which case they never change.
+pub const EMBEDDED: bool = cfg!(feature = "embed-shaders");
+
+/// Every shader of the assets directory with its source, generated by `build.rs`.
+#[cfg(feature = "embed-shaders")]
+const EMBEDDED_SOURCES: &[(&str, &str)] = include!(concat!(env!("OUT_DIR"), "/embedded_shaders.rs"));
+
+pub fn assets_dir() -> PathBuf {
+    PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/assets"))
+}
+
+#[cfg(not(feature = "embed-shaders"))]
+fn cache_dir() -> PathBuf {
+    PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/target/shader-cache"))
+}
+
+// the binary may run far away from the tree it was built in
+#[cfg(feature = "embed-shaders")]
+fn cache_dir() -> PathBuf {
+    std::env::temp_dir().join("testing-shader-cache")
+}
+
+/// Source of `name`, relative to the assets directory.
+#[cfg(not(feature = "embed-shaders"))]
+fn read_source(name: &str) -> std::io::Result<String> {
+    fs::read_to_string(assets_dir().join(name))
+}
+
+#[cfg(feature = "embed-shaders")]
+fn read_source(name: &str) -> std::io::Result<String> {
+    EMBEDDED_SOURCES
+        .iter()
+        .find(|(file, _)| *file == name)
+        .map(|(_, source)| source.to_string())
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "not embedded"))
+}
+
+/// Stage of a shader, from the extension of its file.
+fn kind(name: &str) -> ShaderKind {
+    match name.rsplit('.').next() {
+        Some("vert") => ShaderKind::Vertex,
+        Some("frag") => ShaderKind::Fragment,
+        Some("comp") => ShaderKind::Compute,
+        _ => panic!("Unknown shader stage of {}", name),
+    }
+}
+
+fn stage(kind: ShaderKind) -> ShaderStageFlags {
+    match kind {
+        ShaderKind::Vertex => ShaderStageFlags::VERTEX,
+        ShaderKind::Fragment => ShaderStageFlags::FRAGMENT,
+        ShaderKind::Compute => ShaderStageFlags::COMPUTE,
+        _ => unreachable!(),
+    }
+}
+
+/// Message of the compiler about a line of a shader.
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    /// File the message is about, the shader itself or one of its includes.
+    pub file: String,
+    /// One based, `None` for messages about the whole file.
+    pub line: Option<usize>,
+    /// Message including its severity, e.g. `error: 'foo' : undeclared identifier`.
+    pub message: String,
+}
+
+#[derive(Debug)]
+pub enum ShaderError {
+    /// The shader couldn't be read.
+    Io { name: String, error: std::io::Error },
+    /// The source didn't compile, `sources` of the shader and its includes are kept
+    /// to show the offending lines.
+    Compile {
+        name: String,
+        sources: HashMap<String, String>,
+        diagnostics: Vec<Diagnostic>,
+    },
+    /// Shaderc failed for reasons unrelated to the source.
+    Compiler { name: String, message: String },
+}
+
+impl ShaderError {
+    /// File the error is about, relative to the assets directory.
+    pub fn name(&self) -> &str {
+        match self {
+            ShaderError::Io { name, .. }
+            | ShaderError::Compile { name, .. }
+            | ShaderError::Compiler { name, .. } => name,
+        }
+    }
+}
+
+impl std::error::Error for ShaderError {}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShaderError::Io { name, error } => write!(f, "Couldn't read {}: {}", name, error),
+            ShaderError::Compiler { name, message } => {
+                write!(f, "Couldn't compile {}: {}", name, message)
+            }
+            ShaderError::Compile {
+                name,
+                sources,
+                diagnostics,
+            } => {
+                write!(f, "Couldn't compile {}:", name)?;
+                for diagnostic in diagnostics {
+                    match diagnostic.line {
+                        Some(line) => {
+                            write!(f, "\n{}:{}: {}", diagnostic.file, line, diagnostic.message)?;
+                            let code = sources
+                                .get(&diagnostic.file)
+                                .and_then(|source| source.lines().nth(line.wrapping_sub(1)));
+                            if let Some(code) = code {
+                                write!(f, "\n{:>5} | {}", line, code)?;
+                            }
+                        }
+                        None => write!(f, "\n{}: {}", diagnostic.file, diagnostic.message)?,
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Splits the log of shaderc into messages, lines look like `mesh.frag:12: error: ...`.
+fn parse_diagnostics(name: &str, log: &str) -> Vec<Diagnostic> {
+    log.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let located = line.split_once(':').and_then(|(file, rest)| {
+                let (number, message) = rest.split_once(':')?;
+                Some((file, number.trim().parse().ok()?, message.trim()))
+            });
+            match located {
+                Some((file, line, message)) => Diagnostic {
+                    file: file.to_string(),
+                    line: Some(line),
+                    message: message.to_string(),
+                },
+                None => Diagnostic {
+                    file: name.to_string(),
+                    line: None,
+                    message: line
+                        .strip_prefix(name)
+                        .map_or(line, |rest| rest.trim_start_matches(':'))
+                        .trim()
+                        .to_string(),
+                },
+            }
+        })
+        .collect()
+}
+
+fn read(name: &str) -> Result<String, ShaderError> {
+    read_source(name).map_err(|error| ShaderError::Io {
+        name: name.to_string(),
+        error,
+    })
+}
+
+/// Source of an `#include`d file, kept in `sources` so the shader tracks it.
+fn resolve_include(
+    requested: &str,
+    sources: &RefCell<HashMap<String, String>>,
+) -> Result<shaderc::ResolvedInclude, String> {
+    let content = read_source(requested).map_err(|e| e.to_string())?;
+    sources.borrow_mut().insert(requested.to_string(), content.clone());
+    Ok(shaderc::ResolvedInclude {
+        resolved_name: requested.to_string(),
+        content,
+    })
+}
+
+/// Hash of every file a shader was compiled from, by name.
+fn hashes(sources: &HashMap<String, String>) -> HashMap<String, u64> {
+    sources
+        .iter()
+        .map(|(file, source)| (file.clone(), hash(source)))
+        .collect()
+}
+
+fn compile_tracked(name: &str, defines: Defines) -> Result<Compiled, ShaderError> {
+    let compiler_error = |message: &str| ShaderError::Compiler {
+        name: name.to_string(),
+        message: message.to_string(),
+    };
+
+    let source = read(name)?;
+    let sources = RefCell::new(HashMap::new());
+    sources.borrow_mut().insert(name.to_string(), source.clone());
+
+    let mut compiler =
+        shaderc::Compiler::new().ok_or_else(|| compiler_error("Couldn't initialize shaderc"))?;
+    let mut options =
+        shaderc::CompileOptions::new().ok_or_else(|| compiler_error("Couldn't initialize shaderc"))?;
+    // Vulkan 1.0, encoded like `VK_MAKE_VERSION`
+    options.set_target_env(shaderc::TargetEnv::Vulkan, 1 << 22);
+    options.set_source_language(shaderc::SourceLanguage::GLSL);
+    options.set_generate_debug_info();
+    options.set_optimization_level(shaderc::OptimizationLevel::Performance);
+    for (name, value) in defines {
+        options.add_macro_definition(name, Some(value));
+    }
+    options.set_include_callback(|requested, _, _, _| resolve_include(requested, &sources));
+
+    let kind = kind(name);
+    let artifact = compiler.compile_into_spirv(&source, kind, name, "main", Some(&options));
+    drop(options);
+    let sources = sources.into_inner();
+
+    match artifact {
+        Ok(artifact) => Ok(Compiled {
+            shader: SpirvShader::new(artifact.as_binary().to_vec(), stage(kind), "main"),
+            files: hashes(&sources),
+        }),
+        Err(shaderc::Error::CompilationError(_, log)) => Err(ShaderError::Compile {
+            name: name.to_string(),
+            diagnostics: parse_diagnostics(name, &log),
+            sources,
+        }),
+        Err(error) => Err(compiler_error(&error.to_string())),
+    }
+}
+
+/// Compiles `name` from the assets directory with `defines`, bypassing the cache.
+pub fn compile(name: &str, defines: Defines) -> Result<SpirvShader, ShaderError> {
+    compile_tracked(name, defines).map(|compiled| compiled.shader)
+}
+
+/// Not stable across Rust releases, which only costs a recompile.
+fn hash(value: &impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Magic number every SPIR-V module starts with.
+const SPIRV_MAGIC: u32 = 0x0723_0203;
+
+/// Magic number, version, generator, bound and schema.
+const SPIRV_HEADER_WORDS: usize = 5;
+
+lazy_static::lazy_static! {
+    /// Generator word of an empty shader, shaderc doesn't report its own version but
+    /// stamps the one of the glslang it was built with into every header.
+    static ref GENERATOR: u32 = shaderc::Compiler::new()
+        .and_then(|mut compiler| {
+            let source = "#version 450\nvoid main() {}";
+            compiler
+                .compile_into_spirv(source, ShaderKind::Vertex, "probe.vert", "main", None)
+                .ok()
+        })
+        // a compiler that can't compile the probe won't fill the cache either
+        .map_or(0, |artifact| artifact.as_binary()[2]);
+}
+
+/// Entry for the current source of `name` as compiled by the current shaderc.
+fn cache_path(name: &str, defines: Defines, source: &str) -> PathBuf {
+    let key = hash(&(CACHE_VERSION, *GENERATOR, name, defines, source));
+    cache_dir().join(format!("{}-{:016x}.spv", name.replace('/', "_"), key))
+}
+
+/// Reads `len` bytes off the front of `data`.
+fn take<'a>(data: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+    if data.len() < len {
+        return None;
+    }
+    let (head, tail) = data.split_at(len);
+    *data = tail;
+    Some(head)
+}
+
+fn take_u32(data: &mut &[u8]) -> Option<u32> {
+    Some(u32::from_le_bytes(take(data, 4)?.try_into().ok()?))
+}
+
+fn take_u64(data: &mut &[u8]) -> Option<u64> {
+    Some(u64::from_le_bytes(take(data, 8)?.try_into().ok()?))
+}
+
+/// Entries are the number of files, each file as name length, name and hash,
+/// followed by the SPIR-V words, all little endian.
+fn load_cached(path: &Path, kind: ShaderKind) -> Option<Compiled> {
+    let data = fs::read(path).ok()?;
+    let mut data = &data[..];
+
+    let mut files = HashMap::new();
+    for _ in 0..take_u32(&mut data)? {
+        let len = take_u32(&mut data)? as usize;
+        let file = String::from_utf8(take(&mut data, len)?.to_vec()).ok()?;
+        files.insert(file, take_u64(&mut data)?);
+    }
+
+    // includes aren't part of the key, so they may have changed since
+    for (file, expected) in &files {
+        if hash(&read_source(file).ok()?) != *expected {
+            return None;
+        }
+    }
+
+    if data.len() % 4 != 0 {
+        return None;
+    }
+    let spirv: Vec<u32> = data
+        .chunks_exact(4)
+        .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+        .collect();
+    // a truncated or foreign file isn't worth handing to the driver
+    if spirv.len() < SPIRV_HEADER_WORDS || spirv[0] != SPIRV_MAGIC {
+        return None;
+    }
+
+    Some(Compiled {
+        shader: SpirvShader::new(spirv, stage(kind), "main"),
+        files,
+    })
+}
+
+fn store_cached(path: &Path, compiled: &Compiled) -> std::io::Result<()> {
+    use rendy::shader::Shader;
+
+    // never fails for shaders that are already SPIR-V
+    let spirv = compiled.shader.spirv().unwrap();
+
+    let mut data = Vec::new();
+    data.extend_from_slice(&(compiled.files.len() as u32).to_le_bytes());
+    for (file, hash) in &compiled.files {
+        data.extend_from_slice(&(file.len() as u32).to_le_bytes());
+        data.extend_from_slice(file.as_bytes());
+        data.extend_from_slice(&hash.to_le_bytes());
+    }
+    for word in spirv.iter() {
+        data.extend_from_slice(&word.to_le_bytes());
+    }
+
+    // written next to the entry and renamed over it, so readers only ever see it whole
+    let dir = cache_dir();
+    fs::create_dir_all(&dir)?;
+    let temp = dir.join(format!(
+        "{}.{}.tmp",
+        path.file_name().unwrap().to_string_lossy(),
+        std::process::id()
+    ));
+    fs::File::create(&temp)?.write_all(&data)?;
+    fs::rename(&temp, path).inspect_err(|_| {
+        let _ = fs::remove_file(&temp);
+    })
+}
+
+/// Cached version of `name` if it is still up to date, compiles and caches it otherwise.
+fn load_or_compile(name: &str, defines: Defines) -> Result<Compiled, ShaderError> {
+    let path = cache_path(name, defines, &read(name)?);
+    if let Some(compiled) = load_cached(&path, kind(name)) {
+        return Ok(compiled);
+    }
+
+    let compiled = compile_tracked(name, defines)?;
+    // a cache that can't be written only means compiling again next time
+    if let Err(e) = store_cached(&path, &compiled) {
+        eprintln!("Couldn't cache {}: {}", name, e);
+    }
+    Ok(compiled)
+}
+
+fn key(name: &str, defines: Defines) -> Key {
+    let defines = defines
+        .iter()
+        .map(|&(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+    (name.to_string(), defines)
+}
+
+/// The current version of `name` with `defines`, compiled on first use.
+pub fn get(name: &str, defines: Defines) -> SpirvShader {
+    COMPILED
+        .lock()
+        .unwrap()
+        .entry(key(name, defines))
+        .or_insert_with(|| load_or_compile(name, defines).unwrap_or_else(|e| panic!("{}", e)))
+        .shader
+        .clone()
+}
+
+/// Recompiles every shader in use that was compiled from one of `names`,
+/// be it the shader itself or one of its includes.
+///
+/// Nothing is replaced unless all of them compile, returns whether anything was.
+pub fn reload(names: &[String]) -> Result<bool, ShaderError> {
+    let mut compiled = COMPILED.lock().unwrap();
+
+    let mut reloaded = Vec::new();
+    for (key, entry) in compiled.iter() {
+        if entry.files.keys().any(|file| names.contains(file)) {
+            let (name, defines) = key;
+            let defines: Vec<_> = defines.iter().map(|(n, v)| (n.as_str(), v.as_str())).collect();
+            reloaded.push((key.clone(), load_or_compile(name, &defines)?));
+        }
+    }
+
+    let changed = !reloaded.is_empty();
+    compiled.extend(reloaded);
+    Ok(changed)
+}
+
+/// Reports files of the assets directory that were written to.
+pub struct ShaderWatcher {
+    // events stop once the watcher is dropped
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<notify::Event>>,
+}
+
+impl ShaderWatcher {
+    pub fn new() -> notify::Result<Self> {
+        let (sender, events) = channel();
+        let mut watcher = notify::recommended_watcher(sender)?;
+        watcher.watch(&assets_dir(), RecursiveMode::Recursive)?;
+
+        Ok(Self {
+            _watcher: watcher,
+            events,
+        })
+    }
+
+    /// Names relative to the assets directory of the files changed since the last call.
+    pub fn changed(&self) -> Vec<String> {
+        changed_names(&assets_dir(), self.events.try_iter().filter_map(Result::ok))
+    }
+}
+
+/// Names relative to `assets` of the files written to by `events`, each once.
+fn changed_names(assets: &Path, events: impl Iterator<Item = notify::Event>) -> Vec<String> {
+    let mut names = Vec::new();
+    for event in events {
+        // editors often save by writing a new file and renaming it over the old one
+        if !(event.kind.is_modify() || event.kind.is_create()) {
+            continue;
+        }
+        for path in event.paths {
+            if let Ok(name) = path.strip_prefix(assets) {
+                let name = name.to_string_lossy().into_owned();
+                if !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+        }
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn located(diagnostics: &[Diagnostic]) -> Vec<(&str, Option<usize>, &str)> {
+        diagnostics
+            .iter()
+            .map(|d| (d.file.as_str(), d.line, d.message.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn diagnostics_keep_their_file_and_line() {
+        let log = "\
+mesh.frag:12: error: 'foo' : undeclared identifier
+common.glsl:3: warning: 'bar' : unused
+
+mesh.frag: error: #version: missing
+1 error generated.
+";
+        assert_eq!(
+            located(&parse_diagnostics("mesh.frag", log)),
+            vec![
+                ("mesh.frag", Some(12), "error: 'foo' : undeclared identifier"),
+                ("common.glsl", Some(3), "warning: 'bar' : unused"),
+                ("mesh.frag", None, "error: #version: missing"),
+                ("mesh.frag", None, "1 error generated."),
+            ]
+        );
+    }
+
+    #[test]
+    fn includes_are_read_from_the_assets_directory_and_tracked() {
+        let sources = RefCell::new(HashMap::new());
+        for file in &["common.glsl", "lighting.glsl"] {
+            let include = resolve_include(file, &sources).unwrap();
+            assert_eq!(include.resolved_name, *file);
+            assert_eq!(
+                include.content,
+                fs::read_to_string(assets_dir().join(file)).unwrap()
+            );
+        }
+        assert!(resolve_include("missing.glsl", &sources).is_err());
+
+        let sources = sources.into_inner();
+        let mut files: Vec<_> = hashes(&sources).into_iter().collect();
+        files.sort();
+        assert_eq!(
+            files,
+            vec![
+                ("common.glsl".to_string(), hash(&sources["common.glsl"])),
+                ("lighting.glsl".to_string(), hash(&sources["lighting.glsl"])),
+            ]
+        );
+    }
+
+    fn cached(name: &str, spirv: Vec<u32>, files: &[(&str, u64)]) -> PathBuf {
+        let path = cache_dir().join(format!("test-{}.spv", name));
+        let compiled = Compiled {
+            shader: SpirvShader::new(spirv, ShaderStageFlags::VERTEX, "main"),
+            files: files.iter().map(|&(file, hash)| (file.to_string(), hash)).collect(),
+        };
+        store_cached(&path, &compiled).unwrap();
+        path
+    }
+
+    #[test]
+    fn cache_entries_round_trip_while_their_includes_are_unchanged() {
+        use rendy::shader::Shader;
+
+        let common = hash(&read_source("common.glsl").unwrap());
+        let spirv = vec![SPIRV_MAGIC, 0x0001_0000, 0, 1, 0, 42];
+
+        let path = cached("round-trip", spirv.clone(), &[("common.glsl", common)]);
+        let loaded = load_cached(&path, ShaderKind::Vertex).unwrap();
+        assert_eq!(loaded.shader.spirv().unwrap().into_owned(), spirv);
+        assert_eq!(loaded.files.get("common.glsl"), Some(&common));
+
+        let path = cached("stale-include", spirv, &[("common.glsl", common ^ 1)]);
+        assert!(load_cached(&path, ShaderKind::Vertex).is_none());
+    }
+
+    #[test]
+    fn cache_entries_without_a_spirv_header_are_rejected() {
+        let path = cached("no-magic", vec![0, 0x0001_0000, 0, 1, 0], &[]);
+        assert!(load_cached(&path, ShaderKind::Vertex).is_none());
+
+        let path = cached("truncated", vec![SPIRV_MAGIC, 0x0001_0000], &[]);
+        assert!(load_cached(&path, ShaderKind::Vertex).is_none());
+
+        assert!(load_cached(&cache_dir().join("test-missing.spv"), ShaderKind::Vertex).is_none());
+    }
+
+    #[test]
+    fn written_assets_are_reported_once_by_name() {
+        use notify::event::{AccessKind, CreateKind, DataChange, EventKind, ModifyKind, RemoveKind};
+
+        let assets = Path::new("/project/assets");
+        let event = |kind, paths: &[&str]| {
+            paths.iter().fold(notify::Event::new(kind), |event, path| {
+                event.add_path(PathBuf::from(path))
+            })
+        };
+        let written = EventKind::Modify(ModifyKind::Data(DataChange::Content));
+        let events = vec![
+            event(written, &["/project/assets/mesh.frag", "/project/assets/common.glsl"]),
+            event(EventKind::Create(CreateKind::File), &["/project/assets/sub/fxaa.frag"]),
+            event(written, &["/project/assets/mesh.frag", "/project/src/main.rs"]),
+            event(EventKind::Remove(RemoveKind::File), &["/project/assets/taa.comp"]),
+            event(EventKind::Access(AccessKind::Read), &["/project/assets/mesh.vert"]),
+        ];
+        assert_eq!(
+            changed_names(assets, events.into_iter()),
+            vec!["mesh.frag", "common.glsl", "sub/fxaa.frag"]
+        );
+    }
+
+    #[test]
+    fn every_shader_of_the_assets_directory_is_available() {
+        for entry in fs::read_dir(assets_dir()).unwrap() {
+            let path = entry.unwrap().path();
+            let name = path.file_name().unwrap().to_str().unwrap();
+            assert_eq!(
+                read_source(name).unwrap(),
+                fs::read_to_string(&path).unwrap(),
+                "{}",
+                name
+            );
+        }
+        assert!(read_source("missing.frag").is_err());
+    }
+
+    #[test]
+    fn compile_errors_show_the_offending_line() {
+        let error = ShaderError::Compile {
+            name: "mesh.frag".to_string(),
+            sources: vec![("mesh.frag".to_string(), "void main() {\n    foo;\n}".to_string())]
+                .into_iter()
+                .collect(),
+            diagnostics: parse_diagnostics(
+                "mesh.frag",
+                "mesh.frag:2: error: 'foo' : undeclared identifier\n\
+                 common.glsl:9: error: unknown file\n",
+            ),
+        };
+        assert_eq!(
+            error.to_string(),
+            "Couldn't compile mesh.frag:\n\
+             mesh.frag:2: error: 'foo' : undeclared identifier\n\
+             \x20   2 |     foo;\n\
+             common.glsl:9: error: unknown file"
+        );
+    }
+}
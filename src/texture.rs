@@ -0,0 +1,101 @@
+use rendy::{
+    command::QueueId,
+    factory::{Factory, ImageState},
+    hal,
+    texture::{Texture, TextureBuilder},
+};
+
+use std::path::Path;
+
+/// Albedo texture as `Rgba8Srgb` pixels, kept on the CPU until it is uploaded with `build`.
+#[derive(Clone, Debug)]
+pub struct TextureData {
+    pub size: [u32; 2],
+    /// Rows from top to bottom.
+    pub pixels: Vec<u8>,
+}
+
+impl TextureData {
+    /// Plain white texture, sampled by objects without one of their own.
+    pub fn white() -> Self {
+        Self {
+            size: [1, 1],
+            pixels: vec![255; 4],
+        }
+    }
+
+    /// Decodes a PNG or JPEG file, any other format `image` understands works as well.
+    ///
+    /// Not `rendy::texture::image::load_from_image`, the reference renderer samples the pixels
+    /// on the CPU and the `TextureBuilder` it returns keeps them to itself.
+    pub fn load(path: &Path) -> Result<Self, image::ImageError> {
+        let image = image::open(path)?.to_rgba();
+        Ok(Self {
+            size: [image.width(), image.height()],
+            pixels: image.into_raw(),
+        })
+    }
+
+    /// Uploads the texture without mipmaps, sampled linearly with repeating coordinates.
+    pub fn build<B: hal::Backend>(&self, factory: &mut Factory<B>, queue: QueueId) -> Texture<B> {
+        let [width, height] = self.size;
+
+        TextureBuilder::new()
+            .with_kind(hal::image::Kind::D2(width, height, 1, 1))
+            .with_view_kind(hal::image::ViewKind::D2)
+            .with_data_width(width)
+            .with_data_height(height)
+            .with_raw_data(&self.pixels[..], hal::format::Format::Rgba8Srgb)
+            .with_sampler_info(hal::image::SamplerDesc::new(
+                hal::image::Filter::Linear,
+                hal::image::WrapMode::Tile,
+            ))
+            .build(
+                ImageState {
+                    queue,
+                    stage: hal::pso::PipelineStage::FRAGMENT_SHADER,
+                    access: hal::image::Access::SHADER_READ,
+                    layout: hal::image::Layout::ShaderReadOnlyOptimal,
+                },
+                factory,
+            )
+            .unwrap()
+    }
+
+    /// Bilinear sample with repeating coordinates, as `build` sets up the sampler.
+    /// Returns linear color, like sampling an `Rgba8Srgb` image.
+    pub fn sample(&self, uv: [f32; 2]) -> [f32; 4] {
+        let [width, height] = self.size;
+        let x = uv[0] * width as f32 - 0.5;
+        let y = uv[1] * height as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+
+        let texel = |x: f32, y: f32| {
+            let x = (x as i64).rem_euclid(width as i64) as usize;
+            let y = (y as i64).rem_euclid(height as i64) as usize;
+            let i = (y * width as usize + x) * 4;
+            let p = &self.pixels[i..i + 4];
+            [decode_srgb(p[0]), decode_srgb(p[1]), decode_srgb(p[2]), p[3] as f32 / 255.]
+        };
+
+        let (a, b) = (texel(x0, y0), texel(x0 + 1., y0));
+        let (c, d) = (texel(x0, y0 + 1.), texel(x0 + 1., y0 + 1.));
+        let mut color = [0.; 4];
+        for i in 0..4 {
+            let top = a[i] + (b[i] - a[i]) * fx;
+            let bottom = c[i] + (d[i] - c[i]) * fx;
+            color[i] = top + (bottom - top) * fy;
+        }
+        color
+    }
+}
+
+fn decode_srgb(c: u8) -> f32 {
+    let c = c as f32 / 255.;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}